@@ -0,0 +1,357 @@
+// Times the day 2 noun/verb brute force, the day 7 phase setting search and a long running loop
+// on the interpreter this crate started out with, on Computer and on Computer with a decode cache
+// shared by all Computers running the same program. Run with `cargo bench`.
+use advent_of_code_2019::computer::decode_cache::DecodeCache;
+use advent_of_code_2019::computer::program::Program;
+use advent_of_code_2019::computer::{Computer, State};
+use std::time::{Duration, Instant};
+
+const ROUNDS: u32 = 5;
+
+// The original Computer, before relative mode and everything after it, as a baseline. It decodes
+// every instruction from scratch on each step.
+mod baseline {
+    use std::convert::TryInto;
+
+    const PARAM_MODE_POSITION: usize = 0;
+    const PARAM_MODE_IMMEDIATE: usize = 1;
+
+    enum OpCode {
+        Add { a: i64, b: i64, r: usize },
+        Multiply { a: i64, b: i64, r: usize },
+        Input { r: usize },
+        Output { a: i64 },
+        JumpIfTrue { a: i64, d: usize },
+        JumpIfFalse { a: i64, d: usize },
+        LessThan { a: i64, b: i64, r: usize },
+        Equals { a: i64, b: i64, r: usize },
+        Halt,
+    }
+
+    pub struct Computer {
+        pc: usize,
+        mem: Vec<i64>,
+        inputs: Vec<i64>,
+        output: i64,
+    }
+
+    impl Computer {
+        pub fn new(mem: Vec<i64>) -> Self {
+            Self {
+                pc: 0,
+                mem,
+                inputs: vec![],
+                output: 0,
+            }
+        }
+
+        fn decode_instruction(&self) -> (OpCode, usize) {
+            match self.mem[self.pc] % 100 {
+                1 => (
+                    OpCode::Add {
+                        a: self.get_param(0, false),
+                        b: self.get_param(1, false),
+                        r: self.get_param(2, true) as usize,
+                    },
+                    4,
+                ),
+                2 => (
+                    OpCode::Multiply {
+                        a: self.get_param(0, false),
+                        b: self.get_param(1, false),
+                        r: self.get_param(2, true) as usize,
+                    },
+                    4,
+                ),
+                3 => (
+                    OpCode::Input {
+                        r: self.get_param(0, true) as usize,
+                    },
+                    2,
+                ),
+                4 => (
+                    OpCode::Output {
+                        a: self.get_param(0, false),
+                    },
+                    2,
+                ),
+                5 => (
+                    OpCode::JumpIfTrue {
+                        a: self.get_param(0, false),
+                        d: self.get_param(1, false) as usize,
+                    },
+                    3,
+                ),
+                6 => (
+                    OpCode::JumpIfFalse {
+                        a: self.get_param(0, false),
+                        d: self.get_param(1, false) as usize,
+                    },
+                    3,
+                ),
+                7 => (
+                    OpCode::LessThan {
+                        a: self.get_param(0, false),
+                        b: self.get_param(1, false),
+                        r: self.get_param(2, true) as usize,
+                    },
+                    4,
+                ),
+                8 => (
+                    OpCode::Equals {
+                        a: self.get_param(0, false),
+                        b: self.get_param(1, false),
+                        r: self.get_param(2, true) as usize,
+                    },
+                    4,
+                ),
+                99 => (OpCode::Halt, 1),
+                _ => panic!("Illegal opcode"),
+            }
+        }
+
+        fn execute_instruction(&mut self) -> OpCode {
+            let pc_start = self.pc;
+            let (opcode, size) = self.decode_instruction();
+            match opcode {
+                OpCode::Add { a, b, r } => self.mem[r] = a + b,
+                OpCode::Multiply { a, b, r } => self.mem[r] = a * b,
+                OpCode::Input { r } => self.mem[r] = self.inputs.remove(0),
+                OpCode::Output { a } => self.output = a,
+                OpCode::JumpIfTrue { a, d } => {
+                    if a != 0 {
+                        self.pc = d;
+                    }
+                }
+                OpCode::JumpIfFalse { a, d } => {
+                    if a == 0 {
+                        self.pc = d;
+                    }
+                }
+                OpCode::LessThan { a, b, r } => self.mem[r] = if a < b { 1 } else { 0 },
+                OpCode::Equals { a, b, r } => self.mem[r] = if a == b { 1 } else { 0 },
+                OpCode::Halt => return opcode,
+            }
+            if self.pc == pc_start {
+                self.pc += size;
+            }
+            opcode
+        }
+
+        pub fn run_program(&mut self, inputs: Vec<i64>) -> i64 {
+            self.inputs = inputs;
+            while !matches!(self.execute_instruction(), OpCode::Halt) {}
+            self.output
+        }
+
+        // Returns (halted, output).
+        pub fn run_until_output(&mut self, inputs: Vec<i64>) -> (bool, i64) {
+            self.inputs = inputs;
+            loop {
+                match self.execute_instruction() {
+                    OpCode::Halt => return (true, self.output),
+                    OpCode::Output { .. } => return (false, self.output),
+                    _ => (),
+                }
+            }
+        }
+
+        fn get_param(&self, index: usize, raw: bool) -> i64 {
+            let flag = (((self.mem[self.pc] / 100) / i64::pow(10, index.try_into().unwrap())) % 10)
+                as usize;
+            let val = self.mem[self.pc + index + 1];
+            if raw || flag == PARAM_MODE_IMMEDIATE {
+                val
+            } else if flag == PARAM_MODE_POSITION {
+                self.mem[val as usize]
+            } else {
+                panic!("Illegal param mode {}", flag)
+            }
+        }
+
+        pub fn mem(&self) -> &[i64] {
+            &self.mem
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Interpreter {
+    Baseline,
+    Uncached,
+    Cached,
+}
+
+const INTERPRETERS: [Interpreter; 3] = [
+    Interpreter::Baseline,
+    Interpreter::Uncached,
+    Interpreter::Cached,
+];
+
+// Computer running program, with cache if the interpreter is Cached.
+fn computer(interpreter: Interpreter, program: Vec<i64>, cache: &DecodeCache<i64>) -> Computer {
+    let c = Computer::new(program);
+    match interpreter {
+        Interpreter::Cached => c.with_decode_cache(cache.clone()),
+        _ => c,
+    }
+}
+
+// Runs program to completion and returns its memory.
+fn run(interpreter: Interpreter, program: Vec<i64>, cache: &DecodeCache<i64>) -> Vec<i64> {
+    match interpreter {
+        Interpreter::Baseline => {
+            let mut c = baseline::Computer::new(program);
+            c.run_program(vec![]);
+            c.mem().to_vec()
+        }
+        _ => {
+            let mut c = computer(interpreter, program, cache);
+            c.run_program(vec![]);
+            c.mem().clone()
+        }
+    }
+}
+
+fn load(path: &str) -> Vec<i64> {
+    Program::load(path)
+        .unwrap_or_else(|e| panic!("Can't load {}: {}", path, e))
+        .into_words()
+}
+
+fn day2(program: &[i64], interpreter: Interpreter) -> i64 {
+    let cache = DecodeCache::new(program);
+    for noun in 0..100 {
+        for verb in 0..100 {
+            let mut mem = program.to_vec();
+            mem[1] = noun;
+            mem[2] = verb;
+            if run(interpreter, mem, &cache)[0] == 19690720 {
+                return 100 * noun + verb;
+            }
+        }
+    }
+    panic!("No solution found");
+}
+
+fn permutations(values: &[i64]) -> Vec<Vec<i64>> {
+    if values.len() <= 1 {
+        return vec![values.to_vec()];
+    }
+    let mut result = vec![];
+    for (i, first) in values.iter().enumerate() {
+        let mut rest = values.to_vec();
+        rest.remove(i);
+        for mut p in permutations(&rest) {
+            p.insert(0, *first);
+            result.push(p);
+        }
+    }
+    result
+}
+
+// Runs the amplifiers in a feedback loop until the last one halts and returns its last output.
+fn thrust(
+    program: &[i64],
+    phases: &[i64],
+    interpreter: Interpreter,
+    cache: &DecodeCache<i64>,
+) -> i64 {
+    if let Interpreter::Baseline = interpreter {
+        // The baseline takes the phase along with the first signal, as day 7 used to.
+        let mut amplifiers: Vec<baseline::Computer> = phases
+            .iter()
+            .map(|_| baseline::Computer::new(program.to_vec()))
+            .collect();
+        let mut signal = 0;
+        for round in 0.. {
+            for (c, phase) in amplifiers.iter_mut().zip(phases) {
+                let inputs = if round == 0 {
+                    vec![*phase, signal]
+                } else {
+                    vec![signal]
+                };
+                let (halted, output) = c.run_until_output(inputs);
+                if halted {
+                    return signal;
+                }
+                signal = output;
+            }
+        }
+        unreachable!();
+    }
+    let mut amplifiers: Vec<Computer> = phases
+        .iter()
+        .map(|phase| {
+            let mut c = computer(interpreter, program.to_vec(), cache);
+            c.push_input(*phase);
+            c
+        })
+        .collect();
+    let mut signal = 0;
+    loop {
+        for c in amplifiers.iter_mut() {
+            c.push_input(signal);
+            match c.resume().unwrap() {
+                State::Output(value) => signal = value,
+                State::Halted => return signal,
+                State::NeedsInput => panic!("Amplifier needs more input"),
+            }
+        }
+    }
+}
+
+fn day7(program: &[i64], interpreter: Interpreter) -> (i64, i64) {
+    let cache = DecodeCache::new(program);
+    let search = |phases: &[i64]| {
+        permutations(phases)
+            .iter()
+            .map(|p| thrust(program, p, interpreter, &cache))
+            .max()
+            .unwrap()
+    };
+    (search(&[0, 1, 2, 3, 4]), search(&[5, 6, 7, 8, 9]))
+}
+
+// A single long running Computer, counting to a million in a loop.
+fn count(interpreter: Interpreter) -> i64 {
+    let program = vec![
+        1001, 12, 1, 12, 1007, 12, 1_000_000, 13, 1005, 13, 0, 99, 0, 0,
+    ];
+    let cache = DecodeCache::new(&program);
+    run(interpreter, program.clone(), &cache)[12]
+}
+
+// Best of ROUNDS runs, to keep noise from other processes out. Speedups are relative to the
+// baseline, and for the cache also to Computer without it. Building the caches is included.
+fn time<T: PartialEq + std::fmt::Debug>(name: &str, mut f: impl FnMut(Interpreter) -> T) {
+    let mut best = [Duration::MAX; INTERPRETERS.len()];
+    let mut answers = vec![];
+    for _ in 0..ROUNDS {
+        for (i, interpreter) in INTERPRETERS.iter().enumerate() {
+            let start = Instant::now();
+            answers.push(f(*interpreter));
+            best[i] = best[i].min(start.elapsed());
+        }
+    }
+    assert!(answers.windows(2).all(|w| w[0] == w[1]));
+    let speedup = |i: usize| best[0].as_secs_f64() / best[i].as_secs_f64();
+    println!(
+        "{:<8} baseline {:>10.3?}  uncached {:>10.3?} ({:.2}x)  cached {:>10.3?} ({:.2}x, {:.2}x)",
+        name,
+        best[0],
+        best[1],
+        speedup(1),
+        best[2],
+        speedup(2),
+        best[1].as_secs_f64() / best[2].as_secs_f64()
+    );
+}
+
+fn main() {
+    let day2_program = load("input/day2.txt");
+    let day7_program = load("input/day7.txt");
+    time("day 2", |interpreter| day2(&day2_program, interpreter));
+    time("day 7", |interpreter| day7(&day7_program, interpreter));
+    time("count", count);
+}
@@ -0,0 +1,404 @@
+use advent_of_code_2019::computer::disassembler::disassemble_line;
+use advent_of_code_2019::computer::program::Program;
+use advent_of_code_2019::computer::{Computer, State};
+use std::collections::HashSet;
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+// Number of executed instructions that can be stepped back over.
+const HISTORY: usize = 1_000_000;
+
+const HELP: &str = "\
+Commands:
+  s [n]             step n instructions (default 1)
+  c                 continue until a breakpoint, watchpoint, halt or missing input
+  rs [n]            step n instructions backwards (default 1)
+  rw <addr>         run backwards to just before the last write to addr
+  b <addr>          break when pc reaches addr
+  bo <op>           break before executing an opcode, given as number or mnemonic
+  w <addr>          stop after a write to addr
+  del               delete all breakpoints and watchpoints
+  x <addr> [n]      inspect n memory words (default 1)
+  p <addr> <value>  poke value into memory
+  i <value>...      queue input values
+  r                 show pc, relative base and pending inputs
+  l [n]             list n instructions from pc (default 5)
+  prof [n]          show instruction counts and the n hottest addresses (default 10)
+  q                 quit";
+
+struct Debugger {
+    computer: Computer,
+    breakpoints: HashSet<usize>,
+    opcode_breakpoints: HashSet<String>,
+    watchpoints: HashSet<usize>,
+}
+
+impl Debugger {
+    fn new(program: Vec<i64>) -> Self {
+        Self {
+            computer: Computer::new(program).with_profiler().with_history(HISTORY),
+            breakpoints: HashSet::new(),
+            opcode_breakpoints: HashSet::new(),
+            watchpoints: HashSet::new(),
+        }
+    }
+
+    fn line_at(&self, address: usize) -> String {
+        let mem = self.computer.mem();
+        if address < mem.len() {
+            disassemble_line(mem, address).to_string()
+        } else {
+            format!("{:>6}: db 0", address)
+        }
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        let pc = self.computer.pc();
+        let opcode = self.computer.peek(pc) % 100;
+        let mnemonic = if pc < self.computer.mem().len() {
+            disassemble_line(self.computer.mem(), pc).mnemonic()
+        } else {
+            "db"
+        };
+        self.breakpoints.contains(&pc)
+            || self.opcode_breakpoints.contains(&opcode.to_string())
+            || self.opcode_breakpoints.contains(mnemonic)
+    }
+
+    // Executes one instruction and returns true if execution should stop afterwards.
+    fn step(&mut self) -> bool {
+        match self.computer.step() {
+            Ok(None) => (),
+            Ok(Some(State::Output(value))) => println!("output: {}", value),
+            Ok(Some(State::NeedsInput)) => {
+                println!("waiting for input");
+                return true;
+            }
+            Ok(Some(State::Halted)) => {
+                println!("halted");
+                return true;
+            }
+            Err(e) => {
+                println!("error: {}", e);
+                return true;
+            }
+        }
+        if let Some(address) = self.computer.last_write() {
+            if self.watchpoints.contains(&address) {
+                println!(
+                    "watchpoint: [{}] = {}",
+                    address,
+                    self.computer.peek(address)
+                );
+                return true;
+            }
+        }
+        false
+    }
+
+    fn show_registers(&self) {
+        println!(
+            "pc {}  rb {}  inputs {:?}",
+            self.computer.pc(),
+            self.computer.relative_base(),
+            self.computer.pending_inputs()
+        );
+    }
+
+    // Steps until execution stops on its own or reaches a breakpoint. Breakpoints are checked
+    // before every step but the first, so that continuing from a breakpoint moves past it.
+    // Returns true if it stopped at a breakpoint.
+    fn run(&mut self) -> bool {
+        let mut first = true;
+        loop {
+            if !first && self.at_breakpoint() {
+                return true;
+            }
+            first = false;
+            if self.step() {
+                return false;
+            }
+        }
+    }
+
+    // Returns false when the debugger should quit.
+    fn command(&mut self, command: Command) -> Result<bool, String> {
+        match command {
+            Command::Nothing => (),
+            Command::Step(count) => {
+                for _ in 0..count {
+                    if self.step() {
+                        break;
+                    }
+                }
+                println!("{}", self.line_at(self.computer.pc()));
+            }
+            Command::Continue => {
+                if self.run() {
+                    println!("breakpoint");
+                }
+                println!("{}", self.line_at(self.computer.pc()));
+            }
+            Command::StepBack(count) => {
+                for _ in 0..count {
+                    if !self.computer.step_back() {
+                        println!("start of history");
+                        break;
+                    }
+                }
+                println!("{}", self.line_at(self.computer.pc()));
+            }
+            Command::RunBackToWrite(a) => {
+                if !self.computer.run_back_to_write(a) {
+                    println!("no write to {} in history", a);
+                }
+                println!("{}", self.line_at(self.computer.pc()));
+            }
+            Command::Break(a) => {
+                self.breakpoints.insert(a);
+            }
+            Command::BreakOpcode(op) => {
+                self.opcode_breakpoints.insert(op);
+            }
+            Command::Watch(a) => {
+                self.watchpoints.insert(a);
+            }
+            Command::Delete => {
+                self.breakpoints.clear();
+                self.opcode_breakpoints.clear();
+                self.watchpoints.clear();
+            }
+            Command::Examine(start, count) => {
+                for a in start..start + count {
+                    println!("[{}] = {}", a, self.computer.peek(a));
+                }
+            }
+            Command::Poke(a, value) => {
+                self.computer.poke(a, value).map_err(|e| e.to_string())?;
+            }
+            Command::Input(values) => {
+                for value in values {
+                    self.computer.push_input(value);
+                }
+            }
+            Command::Registers => self.show_registers(),
+            Command::List(count) => {
+                let mut a = self.computer.pc();
+                for _ in 0..count {
+                    println!("{}", self.line_at(a));
+                    a += if a < self.computer.mem().len() {
+                        disassemble_line(self.computer.mem(), a).size()
+                    } else {
+                        1
+                    };
+                }
+            }
+            Command::Profile(count) => {
+                let profile = self.computer.profile().unwrap();
+                print!("{}", profile.report(self.computer.mem(), count));
+            }
+            Command::Quit => return Ok(false),
+            Command::Help => println!("{}", HELP),
+        }
+        Ok(true)
+    }
+}
+
+// A parsed command line, see HELP.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    // An empty line.
+    Nothing,
+    Step(usize),
+    Continue,
+    StepBack(usize),
+    RunBackToWrite(usize),
+    Break(usize),
+    BreakOpcode(String),
+    Watch(usize),
+    Delete,
+    // Start address and number of words.
+    Examine(usize, usize),
+    Poke(usize, i64),
+    Input(Vec<i64>),
+    Registers,
+    List(usize),
+    Profile(usize),
+    Quit,
+    Help,
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let number = |i: usize| -> Result<i64, String> {
+        let word = words.get(i).ok_or("Missing argument")?;
+        word.parse::<i64>()
+            .map_err(|e| format!("Bad number '{}': {}", word, e))
+    };
+    let address = |i: usize| -> Result<usize, String> {
+        let value = number(i)?;
+        if value < 0 {
+            return Err(format!("Negative address {}", value));
+        }
+        Ok(value as usize)
+    };
+    // Optional count argument i.
+    let count = |i: usize, default: usize| -> Result<usize, String> {
+        if words.len() > i {
+            address(i)
+        } else {
+            Ok(default)
+        }
+    };
+    Ok(match words.first().copied().unwrap_or("") {
+        "" => Command::Nothing,
+        "s" => Command::Step(count(1, 1)?),
+        "c" => Command::Continue,
+        "rs" => Command::StepBack(count(1, 1)?),
+        "rw" => Command::RunBackToWrite(address(1)?),
+        "b" => Command::Break(address(1)?),
+        "bo" => Command::BreakOpcode(words.get(1).ok_or("Missing argument")?.to_string()),
+        "w" => Command::Watch(address(1)?),
+        "del" => Command::Delete,
+        "x" => Command::Examine(address(1)?, count(2, 1)?),
+        "p" => Command::Poke(address(1)?, number(2)?),
+        "i" => Command::Input((1..words.len()).map(number).collect::<Result<_, _>>()?),
+        "r" => Command::Registers,
+        "l" => Command::List(count(1, 5)?),
+        "prof" => Command::Profile(count(1, 10)?),
+        "q" => Command::Quit,
+        "h" | "help" => Command::Help,
+        other => return Err(format!("Unknown command '{}', try 'h'", other)),
+    })
+}
+
+fn load_program(path: &str) -> Result<Vec<i64>, String> {
+    Program::load(path)
+        .map(Program::into_words)
+        .map_err(|e| format!("Can't load {}: {}", path, e))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: {} program", args[0]);
+        process::exit(1);
+    }
+    let program = load_program(&args[1]).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let mut debugger = Debugger::new(program);
+    debugger.show_registers();
+    println!("{}", debugger.line_at(0));
+    let stdin = io::stdin();
+    loop {
+        print!("(dbg) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        match parse_command(&line).and_then(|command| debugger.command(command)) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use advent_of_code_2019::computer::assembler::assemble;
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_command(""), Ok(Command::Nothing));
+        assert_eq!(parse_command("s"), Ok(Command::Step(1)));
+        assert_eq!(parse_command(" s  3\n"), Ok(Command::Step(3)));
+        assert_eq!(parse_command("c"), Ok(Command::Continue));
+        assert_eq!(parse_command("rs 2"), Ok(Command::StepBack(2)));
+        assert_eq!(parse_command("rw 9"), Ok(Command::RunBackToWrite(9)));
+        assert_eq!(parse_command("b 4"), Ok(Command::Break(4)));
+        assert_eq!(
+            parse_command("bo add"),
+            Ok(Command::BreakOpcode("add".to_string()))
+        );
+        assert_eq!(parse_command("w 15"), Ok(Command::Watch(15)));
+        assert_eq!(parse_command("del"), Ok(Command::Delete));
+        assert_eq!(parse_command("x 7"), Ok(Command::Examine(7, 1)));
+        assert_eq!(parse_command("x 7 3"), Ok(Command::Examine(7, 3)));
+        assert_eq!(parse_command("p 3 -5"), Ok(Command::Poke(3, -5)));
+        assert_eq!(
+            parse_command("i 1 -2 3"),
+            Ok(Command::Input(vec![1, -2, 3]))
+        );
+        assert_eq!(parse_command("i"), Ok(Command::Input(vec![])));
+        assert_eq!(parse_command("r"), Ok(Command::Registers));
+        assert_eq!(parse_command("l"), Ok(Command::List(5)));
+        assert_eq!(parse_command("prof 3"), Ok(Command::Profile(3)));
+        assert_eq!(parse_command("q"), Ok(Command::Quit));
+        assert_eq!(parse_command("help"), Ok(Command::Help));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse_command("b"), Err("Missing argument".to_string()));
+        assert_eq!(parse_command("bo"), Err("Missing argument".to_string()));
+        assert_eq!(parse_command("p 3"), Err("Missing argument".to_string()));
+        assert_eq!(
+            parse_command("b -1"),
+            Err("Negative address -1".to_string())
+        );
+        assert_eq!(
+            parse_command("s x"),
+            Err("Bad number 'x': invalid digit found in string".to_string())
+        );
+        assert_eq!(
+            parse_command("jump"),
+            Err("Unknown command 'jump', try 'h'".to_string())
+        );
+    }
+
+    // Reads a number and outputs it doubled, until it reads 0.
+    fn doubler() -> Vec<i64> {
+        assemble(
+            "
+                loop: in [n]
+                      jz [n], #end
+                      mul [n], #2, [n]
+                      out [n]
+                      jnz #1, #loop
+                end:  hlt
+                n:    db 0
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut debugger = Debugger::new(doubler());
+        debugger.computer.push_input(1);
+        debugger.computer.push_input(2);
+        debugger.breakpoints.insert(9);
+        assert!(debugger.run());
+        assert_eq!(debugger.computer.pc(), 9);
+        // Continuing from the breakpoint moves past it, and stops there again in the next round.
+        assert!(debugger.run());
+        assert_eq!(debugger.computer.pc(), 9);
+        assert_eq!(debugger.computer.drain_outputs(), vec![2]);
+        // A breakpoint at the very first instruction is checked after one step, at the start of
+        // the next round.
+        debugger.opcode_breakpoints.insert("in".to_string());
+        assert!(debugger.run());
+        assert_eq!(debugger.computer.pc(), 0);
+        // Without breakpoints, it runs until it needs input.
+        debugger.breakpoints.clear();
+        debugger.opcode_breakpoints.clear();
+        assert!(!debugger.run());
+        assert_eq!(debugger.computer.drain_outputs(), vec![4]);
+    }
+}
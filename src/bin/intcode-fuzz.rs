@@ -0,0 +1,32 @@
+use advent_of_code_2019::computer::fuzz::fuzz;
+use std::env;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Checks random programs against the reference interpreter until one diverges.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let parse = |i: usize| args.get(i).map(|arg| arg.parse::<u64>());
+    let (cases, seed) = match (parse(1), parse(2)) {
+        (Some(Ok(cases)), None) => (cases, None),
+        (Some(Ok(cases)), Some(Ok(seed))) => (cases, Some(seed)),
+        _ => {
+            eprintln!("usage: {} cases [seed]", args[0]);
+            process::exit(1);
+        }
+    };
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64
+    });
+    println!("seed {}", seed);
+    match fuzz(seed, cases as usize) {
+        Ok(()) => println!("{} programs agree", cases),
+        Err(divergence) => {
+            println!("{}", divergence);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use super::{Computer, State, VmError};
+use std::error::Error;
+use std::fmt;
+
+// Highest value that is treated as an ASCII character.
+const MAX_ASCII: i64 = 127;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsciiError {
+    NotAscii(char),
+    Vm(VmError),
+}
+
+impl fmt::Display for AsciiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AsciiError::NotAscii(c) => write!(f, "Can't send non-ASCII character '{}'", c),
+            AsciiError::Vm(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl Error for AsciiError {}
+
+impl From<VmError> for AsciiError {
+    fn from(error: VmError) -> Self {
+        AsciiError::Vm(error)
+    }
+}
+
+// Everything a program printed during one run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AsciiOutput {
+    // Text split into lines, without newlines. Text not ended by a newline when the program
+    // stopped comes last, e.g. a prompt.
+    pub lines: Vec<String>,
+    // Output values outside the ASCII range, which is how programs report answers.
+    pub values: Vec<i64>,
+    pub halted: bool,
+}
+
+// Wraps a Computer running a program that talks in ASCII text, like the day 17 camera, the day 21
+// springdroid or the day 25 adventure game.
+pub struct AsciiComputer {
+    computer: Computer,
+}
+
+impl AsciiComputer {
+    pub fn new(program: Vec<i64>) -> Self {
+        Self::from_computer(Computer::new(program))
+    }
+
+    pub fn from_computer(computer: Computer) -> Self {
+        Self { computer }
+    }
+
+    pub fn computer(&mut self) -> &mut Computer {
+        &mut self.computer
+    }
+
+    // Queues line as input, encoding each character as its ASCII code followed by a newline.
+    pub fn send_line(&mut self, line: &str) -> Result<(), AsciiError> {
+        if let Some(c) = line.chars().find(|c| !c.is_ascii()) {
+            return Err(AsciiError::NotAscii(c));
+        }
+        for b in line.bytes().chain(Some(b'\n')) {
+            self.computer.push_input(b as i64);
+        }
+        Ok(())
+    }
+
+    // Runs until the program halts or needs more input than was sent.
+    pub fn run(&mut self) -> Result<AsciiOutput, AsciiError> {
+        let mut output = AsciiOutput::default();
+        let mut line = String::new();
+        loop {
+            match self.computer.resume()? {
+                State::Output(10) => output.lines.push(std::mem::take(&mut line)),
+                State::Output(value @ 0..=MAX_ASCII) => line.push(value as u8 as char),
+                State::Output(value) => output.values.push(value),
+                State::NeedsInput => break,
+                State::Halted => {
+                    output.halted = true;
+                    break;
+                }
+            }
+        }
+        if !line.is_empty() {
+            output.lines.push(line);
+        }
+        self.computer.drain_outputs();
+        Ok(output)
+    }
+
+    // Sends each line of script, e.g. a springdroid program, and runs.
+    pub fn run_script(&mut self, script: &[&str]) -> Result<AsciiOutput, AsciiError> {
+        for line in script {
+            self.send_line(line)?;
+        }
+        self.run()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble;
+    use super::*;
+
+    // Prints a prompt, then echoes its input until it reads a '.' and reports 12345.
+    fn echo() -> AsciiComputer {
+        AsciiComputer::new(
+            assemble(
+                "
+                    out #62
+                    out #32
+                loop:
+                    in [c]
+                    eq [c], #46, [t]
+                    jnz [t], #done
+                    out [c]
+                    jz #0, #loop
+                done:
+                    out #12345
+                    hlt
+                c: db 0
+                t: db 0
+                ",
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn lines_and_values() {
+        let mut c = echo();
+        let output = c.run().unwrap();
+        assert_eq!(output.lines, vec!["> "]);
+        assert!(!output.halted);
+        let output = c.run_script(&["hello", "world"]).unwrap();
+        assert_eq!(output.lines, vec!["hello", "world"]);
+        assert_eq!(output.values, vec![]);
+        let output = c.run_script(&["bye."]).unwrap();
+        assert_eq!(
+            output,
+            AsciiOutput {
+                lines: vec!["bye".to_string()],
+                values: vec![12345],
+                halted: true,
+            }
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut c = echo();
+        assert_eq!(c.send_line("smörgås"), Err(AsciiError::NotAscii('ö')));
+        assert!(c.computer().pending_inputs().is_empty());
+        let mut c = AsciiComputer::new(vec![42]);
+        assert!(matches!(c.run(), Err(AsciiError::Vm(_))));
+    }
+}
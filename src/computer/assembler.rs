@@ -0,0 +1,540 @@
+use super::{INSTRUCTIONS, PARAM_MODE_IMMEDIATE, PARAM_MODE_POSITION, PARAM_MODE_RELATIVE};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+// Guards against macros that (indirectly) invoke themselves.
+const MAX_MACRO_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for AsmError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Number(i128),
+    Punct(char),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: String) -> AsmError {
+        AsmError {
+            line: self.line,
+            column: self.column,
+            message,
+        }
+    }
+    fn is_punct(&self, c: char) -> bool {
+        self.tok == Tok::Punct(c)
+    }
+    fn ident(&self) -> Option<&str> {
+        match &self.tok {
+            Tok::Ident(name) => Some(name),
+            _ => None,
+        }
+    }
+}
+
+fn tokenize(line: usize, text: &str) -> Result<Vec<Token>, AsmError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        let start = i;
+        i += 1;
+        let tok = if c.is_whitespace() {
+            continue;
+        } else if c == ';' {
+            break;
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '.' || c == '%' {
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            Tok::Ident(chars[start..i].iter().collect())
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            let digits: String = chars[start..i].iter().collect();
+            Tok::Number(digits.parse::<i128>().map_err(|_| AsmError {
+                line,
+                column,
+                message: format!("Number {} is too large", digits),
+            })?)
+        } else if "[]#,:+-".contains(c) {
+            Tok::Punct(c)
+        } else {
+            return Err(AsmError {
+                line,
+                column,
+                message: format!("Unexpected character '{}'", c),
+            });
+        };
+        tokens.push(Token { tok, line, column });
+    }
+    Ok(tokens)
+}
+
+// Sum of signed numbers and label references, evaluated once all labels are known.
+struct Expr {
+    terms: Vec<(bool, Token)>,
+}
+
+impl Expr {
+    fn negate(mut self) -> Self {
+        for term in &mut self.terms {
+            term.0 = !term.0;
+        }
+        self
+    }
+    fn eval(&self, labels: &HashMap<String, usize>) -> Result<i64, AsmError> {
+        let mut sum: i128 = 0;
+        for (negative, token) in &self.terms {
+            let value = match &token.tok {
+                Tok::Number(n) => *n,
+                Tok::Ident(name) => *labels
+                    .get(name)
+                    .ok_or_else(|| token.error(format!("Undefined label '{}'", name)))?
+                    as i128,
+                Tok::Punct(_) => unreachable!(),
+            };
+            sum += if *negative { -value } else { value };
+        }
+        let first = &self.terms[0].1;
+        i64::try_from(sum).map_err(|_| first.error(format!("Value {} is out of range", sum)))
+    }
+}
+
+// Parses a complete expression, reporting errors relative to the token before it.
+fn parse_expr(tokens: &[Token], before: &Token) -> Result<Expr, AsmError> {
+    let mut terms = vec![];
+    let mut i = 0;
+    loop {
+        let mut negative = false;
+        if terms.is_empty() {
+            if i < tokens.len() && tokens[i].is_punct('-') {
+                negative = true;
+                i += 1;
+            }
+        } else {
+            match tokens.get(i) {
+                Some(t) if t.is_punct('+') => (),
+                Some(t) if t.is_punct('-') => negative = true,
+                Some(t) => return Err(t.error("Expected '+' or '-'".to_string())),
+                None => break,
+            }
+            i += 1;
+            if i < tokens.len() && tokens[i].is_punct('-') {
+                negative = !negative;
+                i += 1;
+            }
+        }
+        match tokens.get(i) {
+            Some(t) if matches!(t.tok, Tok::Number(_) | Tok::Ident(_)) => {
+                terms.push((negative, t.clone()));
+                i += 1;
+            }
+            Some(t) => return Err(t.error("Expected number or label".to_string())),
+            None => {
+                let last = tokens.last().unwrap_or(before);
+                return Err(last.error("Expected number or label".to_string()));
+            }
+        }
+    }
+    Ok(Expr { terms })
+}
+
+fn parse_operand(tokens: &[Token], before: &Token) -> Result<(i64, Expr), AsmError> {
+    let first = tokens
+        .first()
+        .ok_or_else(|| before.error("Missing operand".to_string()))?;
+    match &first.tok {
+        Tok::Punct('[') => match tokens.last() {
+            Some(last) if tokens.len() > 1 && last.is_punct(']') => Ok((
+                PARAM_MODE_POSITION as i64,
+                parse_expr(&tokens[1..tokens.len() - 1], first)?,
+            )),
+            _ => Err(first.error("Missing ']'".to_string())),
+        },
+        Tok::Punct('#') => Ok((
+            PARAM_MODE_IMMEDIATE as i64,
+            parse_expr(&tokens[1..], first)?,
+        )),
+        Tok::Ident(name) if name == "rb" => {
+            let offset = match tokens.get(1) {
+                None => Expr {
+                    terms: vec![(
+                        false,
+                        Token {
+                            tok: Tok::Number(0),
+                            ..first.clone()
+                        },
+                    )],
+                },
+                Some(t) if t.is_punct('+') => parse_expr(&tokens[2..], t)?,
+                Some(t) if t.is_punct('-') => parse_expr(&tokens[2..], t)?.negate(),
+                Some(t) => return Err(t.error("Expected '+' or '-' after 'rb'".to_string())),
+            };
+            Ok((PARAM_MODE_RELATIVE as i64, offset))
+        }
+        _ => Err(first.error("Expected [address], #value or rb+offset".to_string())),
+    }
+}
+
+// Splits tokens on top level commas. An empty list gives no parts.
+fn split_commas(tokens: &[Token]) -> Vec<&[Token]> {
+    if tokens.is_empty() {
+        return vec![];
+    }
+    tokens.split(|t| t.is_punct(',')).collect()
+}
+
+enum Item {
+    Instruction {
+        opcode: i64,
+        operands: Vec<(i64, Expr)>,
+    },
+    Data(Vec<Expr>),
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Vec<Token>>,
+}
+
+#[derive(Default)]
+struct Assembler {
+    labels: HashMap<String, usize>,
+    macros: HashMap<String, Macro>,
+    items: Vec<Item>,
+    address: usize,
+}
+
+impl Assembler {
+    fn statement(&mut self, tokens: &[Token], depth: usize) -> Result<(), AsmError> {
+        let mut tokens = tokens;
+        // Address annotation as printed by the disassembler.
+        if let [number, colon, ..] = tokens {
+            if let (Tok::Number(n), true) = (&number.tok, colon.is_punct(':')) {
+                if *n != self.address as i128 {
+                    return Err(number.error(format!(
+                        "Address {} doesn't match location {}",
+                        n, self.address
+                    )));
+                }
+                tokens = &tokens[2..];
+            }
+        }
+        while let [label, colon, ..] = tokens {
+            match label.ident() {
+                Some(name) if colon.is_punct(':') => {
+                    if name == "rb" || name.starts_with('%') {
+                        return Err(label.error(format!("'{}' is reserved", name)));
+                    }
+                    if self.labels.insert(name.to_string(), self.address).is_some() {
+                        return Err(label.error(format!("Label '{}' is already defined", name)));
+                    }
+                    tokens = &tokens[2..];
+                }
+                _ => break,
+            }
+        }
+        let (first, rest) = match tokens.split_first() {
+            Some(split) => split,
+            None => return Ok(()),
+        };
+        let name = first
+            .ident()
+            .ok_or_else(|| first.error("Expected instruction".to_string()))?;
+        if name == "db" {
+            let values = split_commas(rest)
+                .into_iter()
+                .map(|t| parse_expr(t, first))
+                .collect::<Result<Vec<Expr>, AsmError>>()?;
+            if values.is_empty() {
+                return Err(first.error("'db' needs at least one value".to_string()));
+            }
+            self.address += values.len();
+            self.items.push(Item::Data(values));
+        } else if let Some((opcode, _, params)) = INSTRUCTIONS.iter().find(|i| i.1 == name) {
+            let operands = split_commas(rest)
+                .into_iter()
+                .map(|t| parse_operand(t, first))
+                .collect::<Result<Vec<(i64, Expr)>, AsmError>>()?;
+            if operands.len() != *params {
+                return Err(first.error(format!(
+                    "'{}' takes {} operands, found {}",
+                    name,
+                    params,
+                    operands.len()
+                )));
+            }
+            self.address += params + 1;
+            self.items.push(Item::Instruction {
+                opcode: *opcode,
+                operands,
+            });
+        } else if self.macros.contains_key(name) {
+            self.expand(first, rest, depth)?;
+        } else {
+            return Err(first.error(format!("Unknown instruction '{}'", name)));
+        }
+        Ok(())
+    }
+
+    fn expand(&mut self, name: &Token, args: &[Token], depth: usize) -> Result<(), AsmError> {
+        if depth >= MAX_MACRO_DEPTH {
+            return Err(name.error("Macros nested too deeply".to_string()));
+        }
+        let m = &self.macros[name.ident().unwrap()];
+        let args = split_commas(args);
+        if args.len() != m.params.len() {
+            return Err(name.error(format!(
+                "Macro takes {} arguments, found {}",
+                m.params.len(),
+                args.len()
+            )));
+        }
+        let lines: Vec<Vec<Token>> = m
+            .body
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .flat_map(|t| {
+                        match t.ident().and_then(|n| m.params.iter().position(|p| p == n)) {
+                            Some(i) => args[i].to_vec(),
+                            None => vec![t.clone()],
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        for line in lines {
+            self.statement(&line, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    fn encode(&self) -> Result<Vec<i64>, AsmError> {
+        let mut program = Vec::with_capacity(self.address);
+        for item in &self.items {
+            match item {
+                Item::Instruction { opcode, operands } => {
+                    let modes: i64 = operands
+                        .iter()
+                        .enumerate()
+                        .map(|(i, (mode, _))| mode * i64::pow(10, i as u32 + 2))
+                        .sum();
+                    program.push(opcode + modes);
+                    for (_, expr) in operands {
+                        program.push(expr.eval(&self.labels)?);
+                    }
+                }
+                Item::Data(values) => {
+                    for expr in values {
+                        program.push(expr.eval(&self.labels)?);
+                    }
+                }
+            }
+        }
+        Ok(program)
+    }
+}
+
+// Assembles source text into an Intcode program.
+//
+// Each line holds optional labels (`loop:`), then an instruction with operands written as
+// `[address]`, `#value` or `rb+offset`, or a `db 1, 2, 3` data directive. Values can be numbers,
+// labels and sums of those, e.g. `[table+2]`. Comments start with `;`. Macros are defined with
+// `%macro name param, ...` up to `%endmacro` and invoked like instructions. Disassembler listings
+// are accepted as source, including their address prefixes.
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    let mut asm = Assembler::default();
+    let mut recording: Option<(Token, String, Macro)> = None;
+    for (i, text) in source.lines().enumerate() {
+        let tokens = tokenize(i + 1, text)?;
+        let directive = tokens.first().and_then(|t| t.ident());
+        if let Some((_, _, m)) = &mut recording {
+            if directive == Some("%endmacro") {
+                let (_, name, m) = recording.take().unwrap();
+                asm.macros.insert(name, m);
+            } else if directive == Some("%macro") {
+                return Err(tokens[0].error("Macro definitions can't be nested".to_string()));
+            } else {
+                m.body.push(tokens);
+            }
+        } else if directive == Some("%macro") {
+            let name = tokens
+                .get(1)
+                .and_then(|t| t.ident().map(|n| n.to_string()))
+                .ok_or_else(|| tokens[0].error("Expected macro name".to_string()))?;
+            let params = split_commas(&tokens[2..])
+                .into_iter()
+                .map(|p| match p {
+                    [t] if t.ident().is_some() => Ok(t.ident().unwrap().to_string()),
+                    _ => Err(p
+                        .first()
+                        .unwrap_or(&tokens[1])
+                        .error("Expected parameter name".to_string())),
+                })
+                .collect::<Result<Vec<String>, AsmError>>()?;
+            recording = Some((
+                tokens[0].clone(),
+                name,
+                Macro {
+                    params,
+                    body: vec![],
+                },
+            ));
+        } else if directive == Some("%endmacro") {
+            return Err(tokens[0].error("'%endmacro' without '%macro'".to_string()));
+        } else {
+            asm.statement(&tokens, 0)?;
+        }
+    }
+    if let Some((start, _, _)) = recording {
+        return Err(start.error("Missing '%endmacro'".to_string()));
+    }
+    asm.encode()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::disassembler::disassemble;
+    use super::super::Computer;
+    use super::*;
+
+    #[test]
+    fn instructions_and_data() {
+        let source = "
+            add [a], #5, [b]   ; b = a + 5
+            out [b]
+            hlt
+        a:  db 37
+        b:  db 0
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(program, vec![1001, 7, 5, 8, 4, 8, 99, 37, 0]);
+        assert_eq!(Computer::new(program).run_program(vec![]), 42);
+    }
+
+    #[test]
+    fn relative_operands_and_expressions() {
+        let source = "
+            arb #table+1
+            out rb+1
+            out rb-1
+            out rb
+            hlt
+        table: db 10, 20, 30, -40
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            Computer::new(program).run_to_halt(vec![]),
+            Ok(vec![30, 10, 20])
+        );
+    }
+
+    #[test]
+    fn macros() {
+        let source = "
+        %macro inc x
+            add x, #1, x
+        %endmacro
+        %macro twice x
+            inc x
+            inc x
+        %endmacro
+            twice [n]
+            out [n]
+            hlt
+        n:  db 40
+        ";
+        let program = assemble(source).unwrap();
+        assert_eq!(Computer::new(program).run_program(vec![]), 42);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source: &str| {
+            let e = assemble(source).unwrap_err();
+            (e.line, e.column, e.message)
+        };
+        assert_eq!(
+            error("  hlt\n  add [a], #1\n"),
+            (2, 3, "'add' takes 3 operands, found 2".to_string())
+        );
+        assert_eq!(
+            error("out [missing]"),
+            (1, 6, "Undefined label 'missing'".to_string())
+        );
+        assert_eq!(
+            error("a: hlt\na: hlt"),
+            (2, 1, "Label 'a' is already defined".to_string())
+        );
+        assert_eq!(
+            error("jmp [0]"),
+            (1, 1, "Unknown instruction 'jmp'".to_string())
+        );
+        assert_eq!(
+            error("out 5"),
+            (1, 5, "Expected [address], #value or rb+offset".to_string())
+        );
+        assert_eq!(
+            error("db 1 $"),
+            (1, 6, "Unexpected character '$'".to_string())
+        );
+        assert_eq!(
+            error("%macro m\nhlt"),
+            (1, 1, "Missing '%endmacro'".to_string())
+        );
+    }
+
+    #[test]
+    fn disassembly_round_trip() {
+        let programs = vec![
+            vec![
+                109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+            ],
+            vec![
+                3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36,
+                98, 0, 0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000,
+                1, 20, 4, 20, 1105, 1, 46, 98, 99,
+            ],
+            vec![42, 301, 22201, 2, 3, -1, 104, i64::MIN, i64::MAX],
+        ];
+        for program in programs {
+            let listing: Vec<String> = disassemble(&program)
+                .iter()
+                .map(|l| l.to_string())
+                .collect();
+            assert_eq!(assemble(&listing.join("\n")), Ok(program));
+        }
+    }
+}
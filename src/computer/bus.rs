@@ -0,0 +1,438 @@
+use super::memory::Memory;
+use super::rng::Rng;
+use super::word::{clamp, Word};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// Hardware mapped into a range of addresses of a Bus. Offsets are relative to the start of the
+// range and below size.
+pub trait Device<W>: fmt::Debug + Send {
+    // Number of addresses the device occupies.
+    fn size(&self) -> usize;
+    // Read by an executing instruction, which may change the device, e.g. take a key press.
+    fn read(&mut self, offset: usize) -> W;
+    // Read without side effects, as done by peek, decoding, tracing and history.
+    fn peek(&self, offset: usize) -> W;
+    fn write(&mut self, offset: usize, value: W);
+}
+
+// Devices are shared, so that the caller can keep a handle to inspect and feed them while a
+// Computer runs.
+pub type DeviceHandle<W> = Arc<Mutex<dyn Device<W>>>;
+
+#[derive(Debug, Clone)]
+struct Mapping<W> {
+    start: usize,
+    size: usize,
+    device: DeviceHandle<W>,
+}
+
+// Memory that sends loads and stores in the address range of an attached device to that device,
+// and everything else to RAM. Clones, and so snapshots, share the devices of the original rather
+// than copying their state. step_back doesn't rewind reads from devices, and doesn't undo writes
+// to them either, as writing the old value back could reseed a Random or reset a Clock.
+#[derive(Debug, Clone)]
+pub struct Bus<M: Memory = Vec<i64>> {
+    ram: M,
+    devices: Vec<Mapping<M::Word>>,
+}
+
+impl<M: Memory> Bus<M> {
+    pub fn new(ram: M) -> Self {
+        Self {
+            ram,
+            devices: vec![],
+        }
+    }
+
+    // Maps device to the addresses from start, shadowing RAM there. Panics if the range overlaps
+    // another device.
+    pub fn with_device(mut self, start: usize, device: DeviceHandle<M::Word>) -> Self {
+        let size = device.lock().unwrap().size();
+        if let Some(other) = self
+            .devices
+            .iter()
+            .find(|m| start < m.start + m.size && m.start < start + size)
+        {
+            panic!(
+                "Device at {}..{} overlaps device at {}..{}",
+                start,
+                start + size,
+                other.start,
+                other.start + other.size
+            );
+        }
+        self.devices.push(Mapping {
+            start,
+            size,
+            device,
+        });
+        self
+    }
+
+    pub fn ram(&self) -> &M {
+        &self.ram
+    }
+
+    // Device mapped at address, and the offset of address into it.
+    fn device(&self, address: usize) -> Option<(&DeviceHandle<M::Word>, usize)> {
+        self.devices
+            .iter()
+            .find(|m| (m.start..m.start + m.size).contains(&address))
+            .map(|m| (&m.device, address - m.start))
+    }
+}
+
+// Buses are equal if their RAM is, and they have the same devices at the same addresses.
+impl<M: Memory> PartialEq for Bus<M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ram == other.ram
+            && self.devices.len() == other.devices.len()
+            && self.devices.iter().zip(&other.devices).all(|(a, b)| {
+                a.start == b.start && a.size == b.size && Arc::ptr_eq(&a.device, &b.device)
+            })
+    }
+}
+
+impl<M: Memory> Eq for Bus<M> {}
+
+impl<M: Memory> Memory for Bus<M> {
+    type Word = M::Word;
+
+    fn get(&self, address: usize) -> M::Word {
+        match self.device(address) {
+            Some((device, offset)) => device.lock().unwrap().peek(offset),
+            None => self.ram.get(address),
+        }
+    }
+    fn load(&self, address: usize) -> M::Word {
+        match self.device(address) {
+            Some((device, offset)) => device.lock().unwrap().read(offset),
+            None => self.ram.load(address),
+        }
+    }
+    // Device addresses don't count towards the memory limit.
+    fn fits(&self, address: usize, limit: usize) -> bool {
+        self.is_device(address) || self.ram.fits(address, limit)
+    }
+    fn set(&mut self, address: usize, value: M::Word) {
+        match self.device(address) {
+            Some((device, offset)) => device.lock().unwrap().write(offset, value),
+            None => self.ram.set(address, value),
+        }
+    }
+    fn is_device(&self, address: usize) -> bool {
+        self.device(address).is_some()
+    }
+    fn len(&self) -> usize {
+        self.ram.len()
+    }
+}
+
+// Reads as the milliseconds since it was created or last written to.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    started: Instant,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W: Word> Device<W> for Clock {
+    fn size(&self) -> usize {
+        1
+    }
+    fn read(&mut self, offset: usize) -> W {
+        self.peek(offset)
+    }
+    fn peek(&self, _: usize) -> W {
+        W::from_i64(self.started.elapsed().as_millis() as i64)
+    }
+    fn write(&mut self, _: usize, _: W) {
+        self.started = Instant::now();
+    }
+}
+
+// Reads as a new non-negative pseudo random number every time. Writing a value reseeds it, so
+// that programs can repeat a sequence.
+#[derive(Debug, Clone)]
+pub struct Random {
+    rng: Rng,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl<W: Word> Device<W> for Random {
+    fn size(&self) -> usize {
+        1
+    }
+    fn read(&mut self, _: usize) -> W {
+        W::from_i64((self.rng.next_u64() >> 1) as i64)
+    }
+    fn peek(&self, offset: usize) -> W {
+        self.clone().read(offset)
+    }
+    fn write(&mut self, _: usize, value: W) {
+        self.rng = Rng::new(clamp(value) as u64);
+    }
+}
+
+// Pixels stored row by row, one word each.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Framebuffer<W: Word = i64> {
+    width: usize,
+    pixels: Vec<W>,
+}
+
+impl<W: Word> Framebuffer<W> {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            pixels: vec![W::from_i64(0); width * height],
+        }
+    }
+
+    // None if x or y is off the screen.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<W> {
+        if x < self.width && y < self.pixels.len() / self.width {
+            Some(self.pixels[y * self.width + x])
+        } else {
+            None
+        }
+    }
+
+    pub fn pixels(&self) -> &[W] {
+        &self.pixels
+    }
+}
+
+// Draws non-zero pixels as '#' and the rest as '.'.
+impl<W: Word> fmt::Display for Framebuffer<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (y, row) in self.pixels.chunks(self.width).enumerate() {
+            if y > 0 {
+                writeln!(f)?;
+            }
+            for pixel in row {
+                let lit = *pixel != W::from_i64(0);
+                write!(f, "{}", if lit { '#' } else { '.' })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Word> Device<W> for Framebuffer<W> {
+    fn size(&self) -> usize {
+        self.pixels.len()
+    }
+    fn read(&mut self, offset: usize) -> W {
+        self.peek(offset)
+    }
+    fn peek(&self, offset: usize) -> W {
+        self.pixels[offset]
+    }
+    fn write(&mut self, offset: usize, value: W) {
+        self.pixels[offset] = value;
+    }
+}
+
+// Reads as the oldest key pressed that wasn't read yet, taking it, or -1 if there is none.
+// Writes are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Keyboard<W: Word = i64> {
+    keys: VecDeque<W>,
+}
+
+impl<W: Word> Keyboard<W> {
+    pub fn new() -> Self {
+        Self {
+            keys: VecDeque::new(),
+        }
+    }
+
+    pub fn press(&mut self, key: W) {
+        self.keys.push_back(key);
+    }
+}
+
+impl<W: Word> Device<W> for Keyboard<W> {
+    fn size(&self) -> usize {
+        1
+    }
+    fn read(&mut self, _: usize) -> W {
+        self.keys.pop_front().unwrap_or_else(|| W::from_i64(-1))
+    }
+    fn peek(&self, _: usize) -> W {
+        self.keys
+            .front()
+            .copied()
+            .unwrap_or_else(|| W::from_i64(-1))
+    }
+    fn write(&mut self, _: usize, _: W) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble;
+    use super::super::{Computer, State, VmError};
+    use super::*;
+
+    const KEYBOARD: usize = 1000;
+    const SCREEN: usize = 2000;
+
+    // Copies three key presses to pixels of a 4 by 2 screen, then outputs the next key.
+    fn draw() -> Vec<i64> {
+        assemble(
+            "
+                add [1000], #0, [2000]
+                add [1000], #0, [2001]
+                add [1000], #0, [2005]
+                out [1000]
+                hlt
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn devices() {
+        let keyboard = Arc::new(Mutex::new(Keyboard::new()));
+        let screen = Arc::new(Mutex::new(Framebuffer::new(4, 2)));
+        let bus = Bus::new(draw())
+            .with_device(KEYBOARD, keyboard.clone())
+            .with_device(SCREEN, screen.clone());
+        for key in 1..=3 {
+            keyboard.lock().unwrap().press(key);
+        }
+        let mut c = Computer::from_memory(bus.clone()).with_memory_limit(100);
+        // Peeking doesn't take the key.
+        assert_eq!(c.peek(KEYBOARD), 1);
+        assert_eq!(c.resume(), Ok(State::Output(-1)));
+        assert_eq!(c.resume(), Ok(State::Halted));
+        assert_eq!(screen.lock().unwrap().to_string(), "##..\n.#..");
+        assert_eq!(screen.lock().unwrap().pixel(1, 1), Some(3));
+        assert_eq!(screen.lock().unwrap().pixel(4, 0), None);
+        assert_eq!(screen.lock().unwrap().pixel(0, 2), None);
+        assert_eq!(c.peek(SCREEN + 1), 2);
+        // Device addresses don't grow RAM.
+        assert_eq!(c.mem().ram(), &draw());
+        assert_eq!(c.mem(), &bus);
+        assert_ne!(c.mem(), &Bus::new(draw()));
+    }
+
+    #[test]
+    fn failed_instructions_leave_devices_alone() {
+        let keyboard = Arc::new(Mutex::new(Keyboard::new()));
+        keyboard.lock().unwrap().press(1);
+        let bus = Bus::new(assemble("add [1000], #0, [5000]").unwrap())
+            .with_device(KEYBOARD, keyboard.clone());
+        let mut c = Computer::from_memory(bus).with_memory_limit(100);
+        assert_eq!(
+            c.resume(),
+            Err(VmError::OutOfBounds {
+                pc: 0,
+                instruction: 1001,
+                address: 5000
+            })
+        );
+        assert_eq!(Device::<i64>::peek(&*keyboard.lock().unwrap(), 0), 1);
+    }
+
+    #[test]
+    fn random() {
+        // Outputs two random numbers, reseeds and outputs one more.
+        let program = assemble(
+            "
+                out [100]
+                out [100]
+                add #0, #7, [100]
+                out [100]
+                hlt
+            ",
+        )
+        .unwrap();
+        let run = |seed| {
+            let bus =
+                Bus::new(program.clone()).with_device(100, Arc::new(Mutex::new(Random::new(seed))));
+            Computer::from_memory(bus).run_to_halt(vec![]).unwrap()
+        };
+        let outputs = run(1);
+        assert_eq!(outputs, run(1));
+        assert_ne!(outputs, run(2));
+        assert!(outputs.iter().all(|v| *v >= 0));
+        assert_ne!(outputs[0], outputs[1]);
+        assert_eq!(outputs[2], Device::<i64>::read(&mut Random::new(7), 0));
+        let random = Random::new(3);
+        assert_eq!(
+            Device::<i64>::peek(&random, 0),
+            Device::<i64>::read(&mut random.clone(), 0)
+        );
+    }
+
+    #[test]
+    fn step_back_leaves_devices_alone() {
+        // Reseeds and outputs a random number, then writes to RAM.
+        let program = assemble(
+            "
+                add #0, #7, [100]
+                out [100]
+                add #0, #1, [20]
+                hlt
+            ",
+        )
+        .unwrap();
+        let random = Arc::new(Mutex::new(Random::new(1)));
+        let bus = Bus::new(program).with_device(100, random.clone());
+        let mut c = Computer::from_memory(bus).with_history(10);
+        c.step().unwrap();
+        let seeded = Random::new(7);
+        assert_eq!(
+            c.step(),
+            Ok(Some(State::Output(Device::<i64>::peek(&seeded, 0))))
+        );
+        c.step().unwrap();
+        assert_eq!(c.peek(20), 1);
+        // Undoing the reseed doesn't write the value read before it back to the device.
+        while c.step_back() {}
+        assert_eq!(c.peek(20), 0);
+        let mut expected = seeded;
+        Device::<i64>::read(&mut expected, 0);
+        assert_eq!(c.peek(100), Device::<i64>::peek(&expected, 0));
+    }
+
+    #[test]
+    fn clock() {
+        let mut clock = Clock::new();
+        assert!(Device::<i64>::peek(&clock, 0) < 1000);
+        Device::<i64>::write(&mut clock, 0, 0);
+        assert!(Device::<i64>::read(&mut clock, 0) < 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "overlaps device at 1000..1001")]
+    fn overlapping_devices() {
+        Bus::new(draw())
+            .with_device(KEYBOARD, Arc::new(Mutex::new(Keyboard::new())))
+            .with_device(998, Arc::new(Mutex::new(Framebuffer::<i64>::new(2, 2))));
+    }
+}
@@ -0,0 +1,69 @@
+use super::word::Word;
+use super::{decode, Decoded};
+use std::sync::Arc;
+
+// Decoded instructions of a program image by address, built once and shared by every Computer
+// running that image, like the thousands of Computers of a day 2 search. Cloning only copies a
+// reference to the entries.
+//
+// Each entry keeps the word it was decoded from and is only used while memory at its address
+// still holds that word. So code that modifies itself needs no invalidation, and Computers never
+// copy or change the shared entries.
+#[derive(Debug, Clone)]
+pub struct DecodeCache<W> {
+    entries: Arc<Vec<Option<(W, Decoded)>>>,
+}
+
+impl<W: Word> DecodeCache<W> {
+    // Decodes every word of program that is a valid instruction.
+    pub fn new(program: &[W]) -> Self {
+        let entries = program
+            .iter()
+            .map(|word| Some((*word, decode(word.to_i64()?).ok()?)))
+            .collect();
+        Self {
+            entries: Arc::new(entries),
+        }
+    }
+
+    // How word decodes, if it is the word the entry at address was decoded from.
+    pub(super) fn get(&self, address: usize, word: W) -> Option<Decoded> {
+        match self.entries.get(address) {
+            Some(Some((cached, decoded))) if *cached == word => Some(*decoded),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Computer, State};
+    use super::*;
+
+    #[test]
+    fn entries() {
+        let cache = DecodeCache::new(&[1101i64, 42, 1198, 99]);
+        assert_eq!(cache.get(0, 1101).map(|d| d.size), Some(4));
+        // Words that aren't instructions, words other than the cached one and addresses beyond
+        // the program have no entry.
+        assert!(cache.get(1, 42).is_none());
+        assert!(cache.get(2, 1198).is_none());
+        assert!(cache.get(3, 1101).is_none());
+        assert!(cache.get(4, 99).is_none());
+    }
+
+    #[test]
+    fn shared() {
+        // Outputs its input plus one.
+        let program = vec![3, 9, 101, 1, 9, 9, 4, 9, 99, 0];
+        let cache = DecodeCache::new(&program);
+        for input in 0..3 {
+            let mut c = Computer::new(program.clone()).with_decode_cache(cache.clone());
+            c.push_input(input);
+            assert_eq!(c.resume(), Ok(State::Output(input + 1)));
+        }
+        // Computers running a different program only miss.
+        let mut c = Computer::new(vec![104, 5, 99]).with_decode_cache(cache);
+        assert_eq!(c.run_to_halt(vec![]), Ok(vec![5]));
+    }
+}
@@ -0,0 +1,177 @@
+use super::{
+    instruction_info, param_mode, PARAM_MODE_IMMEDIATE, PARAM_MODE_POSITION, PARAM_MODE_RELATIVE,
+};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Position(i64),
+    Immediate(i64),
+    Relative(i64),
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operand::Position(a) => write!(f, "[{}]", a),
+            Operand::Immediate(v) => write!(f, "#{}", v),
+            Operand::Relative(o) => write!(f, "rb{:+}", o),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Line {
+    Instruction {
+        address: usize,
+        mnemonic: &'static str,
+        operands: Vec<Operand>,
+    },
+    // A word that doesn't decode as an instruction.
+    Data {
+        address: usize,
+        value: i64,
+    },
+}
+
+impl Line {
+    pub fn address(&self) -> usize {
+        match self {
+            Line::Instruction { address, .. } | Line::Data { address, .. } => *address,
+        }
+    }
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Line::Instruction { mnemonic, .. } => mnemonic,
+            Line::Data { .. } => "db",
+        }
+    }
+    // Number of memory words covered by the line.
+    pub fn size(&self) -> usize {
+        match self {
+            Line::Instruction { operands, .. } => operands.len() + 1,
+            Line::Data { .. } => 1,
+        }
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Line::Instruction {
+                address,
+                mnemonic,
+                operands,
+            } => {
+                write!(f, "{:>6}: {}", address, mnemonic)?;
+                for (i, operand) in operands.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { " " } else { ", " }, operand)?;
+                }
+                Ok(())
+            }
+            Line::Data { address, value } => write!(f, "{:>6}: db {}", address, value),
+        }
+    }
+}
+
+// Decodes the instruction at address, or None if the words there aren't a valid instruction.
+// Mode digits beyond the parameter count are rejected so that every decoded line encodes back
+// to exactly the same words.
+fn decode(program: &[i64], address: usize) -> Option<Line> {
+    let instruction = program[address];
+    if instruction < 0 {
+        return None;
+    }
+    let (mnemonic, params) = instruction_info(instruction % 100)?;
+    if instruction / 100 >= i64::pow(10, params as u32) || address + params >= program.len() {
+        return None;
+    }
+    let operands = (0..params)
+        .map(|i| {
+            let value = program[address + i + 1];
+            match param_mode(instruction, i) as usize {
+                PARAM_MODE_POSITION => Some(Operand::Position(value)),
+                PARAM_MODE_IMMEDIATE => Some(Operand::Immediate(value)),
+                PARAM_MODE_RELATIVE => Some(Operand::Relative(value)),
+                _ => None,
+            }
+        })
+        .collect::<Option<Vec<Operand>>>()?;
+    Some(Line::Instruction {
+        address,
+        mnemonic,
+        operands,
+    })
+}
+
+// Disassembles the single line starting at address, which must be within the program.
+pub fn disassemble_line(program: &[i64], address: usize) -> Line {
+    decode(program, address).unwrap_or(Line::Data {
+        address,
+        value: program[address],
+    })
+}
+
+// Linear sweep disassembly of a whole program. Words that can't be decoded, e.g. data or
+// truncated instructions, become data lines and decoding continues at the next word.
+pub fn disassemble(program: &[i64]) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut address = 0;
+    while address < program.len() {
+        let line = disassemble_line(program, address);
+        address += line.size();
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(program: &[i64]) -> Vec<String> {
+        disassemble(program)
+            .iter()
+            .map(|l| l.to_string().trim_start().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn operand_modes() {
+        assert_eq!(
+            listing(&[1201, 12, 5, -3, 99]),
+            vec!["0: add rb+12, #5, [-3]", "4: hlt"]
+        );
+    }
+
+    #[test]
+    fn undecodable_words_are_data() {
+        assert_eq!(
+            listing(&[42, 301, 1, 2, 3, -1, 104]),
+            vec![
+                "0: db 42",
+                "1: db 301",
+                "2: add [2], [3], [-1]",
+                "6: db 104"
+            ]
+        );
+    }
+
+    #[test]
+    fn day9_quine() {
+        let program = [
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        assert_eq!(
+            listing(&program),
+            vec![
+                "0: arb #1",
+                "2: out rb-1",
+                "4: add [100], #1, [100]",
+                "8: eq [100], #16, [101]",
+                "12: jz [101], #0",
+                "15: hlt"
+            ]
+        );
+    }
+}
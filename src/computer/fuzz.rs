@@ -0,0 +1,377 @@
+use super::decode_cache::DecodeCache;
+use super::rng::Rng;
+use super::{Computer, State};
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+// Limits for generated programs, small enough for runaway programs to stop quickly.
+const STEP_LIMIT: u64 = 1000;
+const MEMORY_LIMIT: usize = 4096;
+
+// Opcodes the generator picks from, with the parameter count of each.
+const OPCODES: [(i64, usize); 10] = [
+    (1, 3),
+    (2, 3),
+    (3, 1),
+    (4, 1),
+    (5, 2),
+    (6, 2),
+    (7, 3),
+    (8, 3),
+    (9, 1),
+    (99, 0),
+];
+
+// Generates a random program of well-formed instructions followed by some data, and inputs for
+// it. Parameters mostly point into the program, so instructions read and modify each other. Now
+// and then an illegal mode, an illegal opcode or an extreme value is thrown in to exercise the
+// error paths.
+pub fn generate(rng: &mut Rng) -> (Vec<i64>, Vec<i64>) {
+    let instructions = rng.range(1, 20);
+    let mut program = vec![];
+    for _ in 0..instructions {
+        let (opcode, params) = OPCODES[rng.below(OPCODES.len() as u64) as usize];
+        let mut instruction = opcode;
+        for i in 0..params {
+            let mode = if rng.one_in(50) { 3 } else { rng.range(0, 2) };
+            instruction += mode * [100, 1000, 10000][i];
+        }
+        if rng.one_in(50) {
+            instruction = rng.range(-100, 100);
+        }
+        program.push(instruction);
+        for _ in 0..params {
+            program.push(rng.range(-2, 4 * instructions + 8));
+        }
+    }
+    for _ in 0..rng.range(0, 8) {
+        program.push(rng.range(-5, 50));
+    }
+    for _ in 0..rng.below(3) {
+        let address = rng.below(program.len() as u64) as usize;
+        program[address] = [i64::MAX, i64::MIN, 1 << 40, -(1 << 40)][rng.below(4) as usize];
+    }
+    let inputs = (0..rng.below(6)).map(|_| rng.range(-3, 30)).collect();
+    (program, inputs)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Executed,
+    Output(i64),
+    Halted,
+    NeedsInput,
+    // Any error, with the pc of the failing instruction.
+    Error(usize),
+    // Only Computer can panic. The panic hook has already printed the message.
+    Panicked,
+}
+
+// Straightforward Intcode interpreter written from the puzzle descriptions, to check Computer
+// against. It favors obviously correct over fast, and shares no code with Computer. Like
+// Computer, it treats immediate mode write parameters as position mode, stops with an error on
+// overflow, and grows memory on writes up to a limit.
+struct Reference {
+    mem: Vec<i64>,
+    pc: i64,
+    relative_base: i64,
+    inputs: VecDeque<i64>,
+}
+
+impl Reference {
+    fn load(&self, address: i64) -> Option<i64> {
+        if address < 0 {
+            return None;
+        }
+        Some(self.mem.get(address as usize).copied().unwrap_or(0))
+    }
+
+    fn writable(&self, address: i64) -> bool {
+        address >= 0 && (address as usize) < self.mem.len().max(MEMORY_LIMIT)
+    }
+
+    fn store(&mut self, address: i64, value: i64) -> Option<()> {
+        if !self.writable(address) {
+            return None;
+        }
+        if address as usize >= self.mem.len() {
+            self.mem.resize(address as usize + 1, 0);
+        }
+        self.mem[address as usize] = value;
+        Some(())
+    }
+
+    fn mode(&self, i: u32) -> Option<i64> {
+        let instruction = self.load(self.pc)?;
+        Some(instruction / 10i64.pow(i + 2) % 10)
+    }
+
+    // Value of parameter i, counting from 0.
+    fn param(&self, i: u32) -> Option<i64> {
+        let raw = self.load(self.pc + 1 + i as i64)?;
+        match self.mode(i)? {
+            0 => self.load(raw),
+            1 => Some(raw),
+            2 => self.load(self.relative_base.checked_add(raw)?),
+            _ => None,
+        }
+    }
+
+    // Address parameter i refers to, for writing. Addresses that can't be written fail even
+    // before an input instruction waits for input.
+    fn target(&self, i: u32) -> Option<i64> {
+        let raw = self.load(self.pc + 1 + i as i64)?;
+        let address = match self.mode(i)? {
+            0 | 1 => raw,
+            2 => self.relative_base.checked_add(raw)?,
+            _ => return None,
+        };
+        if !self.writable(address) {
+            return None;
+        }
+        Some(address)
+    }
+
+    fn step(&mut self) -> Step {
+        let pc = self.pc;
+        match self.execute() {
+            Some(step) => step,
+            None => Step::Error(pc as usize),
+        }
+    }
+
+    fn execute(&mut self) -> Option<Step> {
+        let opcode = self.load(self.pc)? % 100;
+        // Modes of all parameters are checked before executing, like the VM does.
+        let params = OPCODES.iter().find(|(op, _)| *op == opcode)?.1 as u32;
+        for i in 0..params {
+            if self.mode(i)? > 2 {
+                return None;
+            }
+        }
+        match opcode {
+            1 => {
+                let (a, b, r) = (self.param(0)?, self.param(1)?, self.target(2)?);
+                self.store(r, a.checked_add(b)?)?;
+                self.pc += 4;
+            }
+            2 => {
+                let (a, b, r) = (self.param(0)?, self.param(1)?, self.target(2)?);
+                self.store(r, a.checked_mul(b)?)?;
+                self.pc += 4;
+            }
+            3 => {
+                let r = self.target(0)?;
+                let input = match self.inputs.pop_front() {
+                    Some(input) => input,
+                    None => return Some(Step::NeedsInput),
+                };
+                self.store(r, input)?;
+                self.pc += 2;
+            }
+            4 => {
+                let a = self.param(0)?;
+                self.pc += 2;
+                return Some(Step::Output(a));
+            }
+            5 | 6 => {
+                let (a, d) = (self.param(0)?, self.param(1)?);
+                if (opcode == 5) == (a != 0) {
+                    if d < 0 {
+                        return None;
+                    }
+                    self.pc = d;
+                } else {
+                    self.pc += 3;
+                }
+            }
+            7 => {
+                let (a, b, r) = (self.param(0)?, self.param(1)?, self.target(2)?);
+                self.store(r, if a < b { 1 } else { 0 })?;
+                self.pc += 4;
+            }
+            8 => {
+                let (a, b, r) = (self.param(0)?, self.param(1)?, self.target(2)?);
+                self.store(r, if a == b { 1 } else { 0 })?;
+                self.pc += 4;
+            }
+            9 => {
+                let a = self.param(0)?;
+                self.relative_base = self.relative_base.checked_add(a)?;
+                self.pc += 2;
+            }
+            99 => return Some(Step::Halted),
+            _ => return None,
+        }
+        Some(Step::Executed)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub program: Vec<i64>,
+    pub inputs: Vec<i64>,
+    // Number of instructions both executed before they disagreed.
+    pub step: u64,
+    pub message: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |values: &[i64]| {
+            values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        writeln!(f, "Divergence at step {}: {}", self.step, self.message)?;
+        writeln!(f, "program {}", join(&self.program))?;
+        write!(f, "inputs {}", join(&self.inputs))
+    }
+}
+
+fn computer_step(c: &mut Computer) -> Step {
+    let pc = c.pc();
+    match panic::catch_unwind(AssertUnwindSafe(|| c.step())) {
+        Ok(Ok(None)) => Step::Executed,
+        Ok(Ok(Some(State::Output(value)))) => Step::Output(value),
+        Ok(Ok(Some(State::Halted))) => Step::Halted,
+        Ok(Ok(Some(State::NeedsInput))) => Step::NeedsInput,
+        Ok(Err(_)) => Step::Error(pc),
+        Err(_) => Step::Panicked,
+    }
+}
+
+// First address where the memories differ, where memory beyond the end reads as zero.
+fn first_difference(a: &[i64], b: &[i64]) -> Option<usize> {
+    (0..a.len().max(b.len())).find(|i| a.get(*i).unwrap_or(&0) != b.get(*i).unwrap_or(&0))
+}
+
+// Runs program on the reference interpreter and in lockstep on Computer, with and without the
+// decode cache, and reports the first step after which their pc, memory or step result differ.
+pub fn check(program: &[i64], inputs: &[i64]) -> Result<(), Divergence> {
+    let mut reference = Reference {
+        mem: program.to_vec(),
+        pc: 0,
+        relative_base: 0,
+        inputs: inputs.iter().copied().collect(),
+    };
+    let computer = |cached| {
+        let mut c = Computer::new(program.to_vec()).with_memory_limit(MEMORY_LIMIT);
+        if cached {
+            c = c.with_decode_cache(DecodeCache::new(program));
+        }
+        for input in inputs {
+            c.push_input(*input);
+        }
+        c
+    };
+    let mut computers = vec![
+        ("Computer", computer(false)),
+        ("cached Computer", computer(true)),
+    ];
+    let diverged = |step, message| {
+        Err(Divergence {
+            program: program.to_vec(),
+            inputs: inputs.to_vec(),
+            step,
+            message,
+        })
+    };
+    for step in 0..STEP_LIMIT {
+        let expected = reference.step();
+        for (name, c) in &mut computers {
+            let actual = computer_step(c);
+            if actual != expected {
+                return diverged(
+                    step,
+                    format!("{} gave {:?}, reference {:?}", name, actual, expected),
+                );
+            }
+            if let Some(address) = first_difference(c.mem(), &reference.mem) {
+                return diverged(
+                    step,
+                    format!(
+                        "[{}] is {} in {}, {} in reference",
+                        address,
+                        c.peek(address),
+                        name,
+                        reference.mem.get(address).unwrap_or(&0)
+                    ),
+                );
+            }
+            if c.pc() as i64 != reference.pc {
+                return diverged(
+                    step,
+                    format!(
+                        "pc is {} in {}, {} in reference",
+                        c.pc(),
+                        name,
+                        reference.pc
+                    ),
+                );
+            }
+        }
+        match expected {
+            Step::Halted | Step::NeedsInput | Step::Error(_) => return Ok(()),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+// Checks cases random programs, generated from seed. Returns the first divergence found.
+pub fn fuzz(seed: u64, cases: usize) -> Result<(), Divergence> {
+    let mut rng = Rng::new(seed);
+    for _ in 0..cases {
+        let (program, inputs) = generate(&mut rng);
+        check(&program, &inputs)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_programs() {
+        if let Err(divergence) = fuzz(2019, 3000) {
+            panic!("{}", divergence);
+        }
+    }
+
+    #[test]
+    fn found_divergences() {
+        // A jump to itself.
+        assert_eq!(check(&[1105, 1, 0], &[]), Ok(()));
+        // A jump that isn't taken, to a negative address.
+        assert_eq!(check(&[1106, 1, -1, 99], &[]), Ok(()));
+        // Relative base overflows.
+        assert_eq!(check(&[109, i64::MAX, 109, 1, 99], &[]), Ok(()));
+        assert_eq!(check(&[109, i64::MAX, 204, 1, 99], &[]), Ok(()));
+    }
+
+    #[test]
+    fn generator_is_deterministic() {
+        let (a, b) = (generate(&mut Rng::new(1)), generate(&mut Rng::new(1)));
+        assert_eq!(a, b);
+        assert_ne!(a, generate(&mut Rng::new(2)));
+    }
+
+    #[test]
+    fn display() {
+        let divergence = Divergence {
+            program: vec![1105, 1, 0],
+            inputs: vec![],
+            step: 0,
+            message: "pc is 3 in Computer, 0 in reference".to_string(),
+        };
+        assert_eq!(
+            divergence.to_string(),
+            "Divergence at step 0: pc is 3 in Computer, 0 in reference\nprogram 1105,1,0\ninputs "
+        );
+    }
+}
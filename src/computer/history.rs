@@ -0,0 +1,164 @@
+use super::memory::Memory;
+use super::word::Word;
+use super::Computer;
+use std::collections::VecDeque;
+
+// What an executed instruction changed, so that it can be undone.
+#[derive(Debug, Clone)]
+pub(super) struct Undo<W> {
+    pub(super) pc: usize,
+    pub(super) relative_base: W,
+    pub(super) output: W,
+    // Length of the output buffer before the instruction.
+    pub(super) outputs: usize,
+    // Written address and the value it held before.
+    pub(super) write: Option<(usize, W)>,
+    pub(super) input: Option<W>,
+}
+
+// Undo log of the most recently executed instructions, oldest first.
+#[derive(Debug, Clone)]
+pub(super) struct History<W> {
+    pub(super) entries: VecDeque<Undo<W>>,
+    pub(super) limit: usize,
+}
+
+impl<W> History<W> {
+    pub(super) fn push(&mut self, undo: Undo<W>) {
+        if self.limit == 0 {
+            return;
+        }
+        if self.entries.len() == self.limit {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(undo);
+    }
+}
+
+impl<W: Word, M: Memory<Word = W>> Computer<W, M> {
+    // Records an undo log of the last limit executed instructions, so that execution can be
+    // stepped backwards. Undoing restores memory, pc, relative base, consumed inputs and buffered
+    // outputs, but can't take back values already passed to an output sink or writes to devices
+    // on a Bus. Memory that grew stays grown, filled with zeros.
+    pub fn with_history(mut self, limit: usize) -> Self {
+        self.history = Some(History {
+            entries: VecDeque::new(),
+            limit,
+        });
+        self
+    }
+
+    // Number of executed instructions that can be undone.
+    pub fn history_len(&self) -> usize {
+        self.history.as_ref().map_or(0, |h| h.entries.len())
+    }
+
+    // Undoes the most recently executed instruction. Returns false if there is nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let undo = match self.history.as_mut().and_then(|h| h.entries.pop_back()) {
+            Some(undo) => undo,
+            None => return false,
+        };
+        if let Some((address, value)) = undo.write.filter(|(a, _)| !self.mem.is_device(*a)) {
+            // The address was written before, so it is within the memory limit.
+            self.write(address, value).unwrap();
+        }
+        if let Some(input) = undo.input {
+            self.inputs.push_front(input);
+        }
+        self.outputs.truncate(undo.outputs);
+        self.output = undo.output;
+        self.relative_base = undo.relative_base;
+        self.pc = undo.pc;
+        self.steps -= 1;
+        self.halted = false;
+        self.last_write = None;
+        true
+    }
+
+    // Steps backwards to just before the most recent write to address, so that the writing
+    // instruction is the next to execute. Returns false if no recorded instruction wrote to it,
+    // leaving the Computer at the start of the history.
+    pub fn run_back_to_write(&mut self, address: usize) -> bool {
+        loop {
+            let wrote = match self.history.as_ref().and_then(|h| h.entries.back()) {
+                Some(undo) => undo.write.map(|(a, _)| a) == Some(address),
+                None => return false,
+            };
+            self.step_back();
+            if wrote {
+                return true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{State, DOUBLER};
+    use super::*;
+
+    #[test]
+    fn step_back_and_forward() {
+        let mut c = Computer::new(DOUBLER.to_vec()).with_history(100);
+        c.push_input(3);
+        c.push_input(5);
+        assert_eq!(c.resume(), Ok(State::Output(6)));
+        let after_first = c.snapshot();
+        assert_eq!(c.resume(), Ok(State::Output(10)));
+        assert_eq!(c.history_len(), 9);
+        for _ in 0..5 {
+            assert!(c.step_back());
+        }
+        assert_eq!(c.snapshot(), after_first);
+        assert_eq!(c.resume(), Ok(State::Output(10)));
+        while c.step_back() {}
+        let mut start = Computer::new(DOUBLER.to_vec());
+        start.push_input(3);
+        start.push_input(5);
+        assert_eq!(c.snapshot(), start.snapshot());
+        assert_eq!(c.run_to_halt(vec![0]), Ok(vec![6, 10]));
+    }
+
+    #[test]
+    fn step_back_from_halt() {
+        let mut c = Computer::new(DOUBLER.to_vec()).with_history(100);
+        assert_eq!(c.run_to_halt(vec![0]), Ok(vec![]));
+        let halted = c.history_len();
+        // Steps after halting aren't recorded.
+        assert_eq!(c.step(), Ok(Some(State::Halted)));
+        assert_eq!(c.history_len(), halted);
+        assert!(c.step_back());
+        assert_eq!(c.pc(), 14);
+        assert_eq!(c.step(), Ok(Some(State::Halted)));
+        assert_eq!(c.history_len(), halted);
+    }
+
+    #[test]
+    fn run_back_to_write() {
+        let mut c = Computer::new(DOUBLER.to_vec()).with_history(100);
+        assert_eq!(c.run_to_halt(vec![3, 5, 0]), Ok(vec![6, 10]));
+        // The last write to 15 was the input of 0.
+        assert!(c.run_back_to_write(15));
+        assert_eq!(c.pc(), 0);
+        assert_eq!(c.peek(15), 10);
+        assert_eq!(c.pending_inputs(), &[0]);
+        // Before that, 15 was doubled.
+        assert!(c.run_back_to_write(15));
+        assert_eq!(c.pc(), 5);
+        assert_eq!(c.peek(15), 5);
+        assert!(!c.run_back_to_write(1000));
+        assert_eq!(c.history_len(), 0);
+        assert_eq!(c.pc(), 0);
+    }
+
+    #[test]
+    fn limit() {
+        let mut c = Computer::new(DOUBLER.to_vec()).with_history(3);
+        assert_eq!(c.run_to_halt(vec![3, 5, 0]), Ok(vec![6, 10]));
+        assert_eq!(c.history_len(), 3);
+        assert!(c.step_back() && c.step_back() && c.step_back());
+        assert!(!c.step_back());
+        assert!(!Computer::new(DOUBLER.to_vec()).step_back());
+    }
+}
@@ -0,0 +1,198 @@
+use super::word::Word;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+// Where a Computer takes its input from once its own input queue is empty.
+pub trait InputSource<W = i64>: Send {
+    // Returns the next input value, or None if there is none right now. The Computer then
+    // reports State::NeedsInput and asks again when resumed.
+    fn next_input(&mut self) -> Option<W>;
+}
+
+// Where a Computer sends its output values.
+pub trait OutputSink<W = i64>: Send {
+    fn send_output(&mut self, value: W);
+}
+
+impl<W, F: FnMut() -> Option<W> + Send> InputSource<W> for F {
+    fn next_input(&mut self) -> Option<W> {
+        self()
+    }
+}
+
+impl<W, F: FnMut(W) + Send> OutputSink<W> for F {
+    fn send_output(&mut self, value: W) {
+        self(value)
+    }
+}
+
+// Blocks until a value arrives. A disconnected channel gives no more input.
+impl<W: Send> InputSource<W> for Receiver<W> {
+    fn next_input(&mut self) -> Option<W> {
+        self.recv().ok()
+    }
+}
+
+// Values sent after the receiver is gone are dropped.
+impl<W: Send> OutputSink<W> for Sender<W> {
+    fn send_output(&mut self, value: W) {
+        let _ = self.send(value);
+    }
+}
+
+// In memory FIFO queue that can be shared between a Computer and its surroundings, e.g. a test
+// harness filling it with input or reading the outputs from it.
+#[derive(Debug, Clone, Default)]
+pub struct Queue(Arc<Mutex<VecDeque<i64>>>);
+
+impl Queue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn push(&self, value: i64) {
+        self.0.lock().unwrap().push_back(value);
+    }
+    pub fn pop(&self) -> Option<i64> {
+        self.0.lock().unwrap().pop_front()
+    }
+    pub fn drain(&self) -> Vec<i64> {
+        self.0.lock().unwrap().drain(..).collect()
+    }
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl InputSource for Queue {
+    fn next_input(&mut self) -> Option<i64> {
+        self.pop()
+    }
+}
+
+impl OutputSink for Queue {
+    fn send_output(&mut self, value: i64) {
+        self.push(value);
+    }
+}
+
+// Reads one number per line from stdin, showing a prompt first. Lines that aren't numbers are
+// reported on stderr and skipped. End of input gives no more input.
+pub struct StdinSource {
+    prompt: String,
+}
+
+impl StdinSource {
+    pub fn new(prompt: &str) -> Self {
+        Self {
+            prompt: prompt.to_string(),
+        }
+    }
+}
+
+impl InputSource for StdinSource {
+    fn next_input(&mut self) -> Option<i64> {
+        let stdin = io::stdin();
+        loop {
+            print!("{}", self.prompt);
+            io::stdout().flush().ok()?;
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).ok()? == 0 {
+                return None;
+            }
+            match line.trim().parse::<i64>() {
+                Ok(value) => return Some(value),
+                Err(e) => eprintln!("Bad input '{}': {}", line.trim(), e),
+            }
+        }
+    }
+}
+
+// Prints one output value per line to stdout.
+pub struct StdoutSink;
+
+impl<W: Word> OutputSink<W> for StdoutSink {
+    fn send_output(&mut self, value: W) {
+        println!("{}", value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Computer, State, DOUBLER};
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    fn doubler() -> Computer {
+        Computer::new(DOUBLER.to_vec())
+    }
+
+    #[test]
+    fn closures() {
+        let mut inputs = vec![3, 2, 1];
+        let seen = Queue::new();
+        let sink = seen.clone();
+        let mut c = doubler()
+            .with_input(move || inputs.pop())
+            .with_output(move |v| sink.push(v * 10));
+        assert_eq!(c.resume(), Ok(State::Output(2)));
+        assert_eq!(c.resume(), Ok(State::Output(4)));
+        assert_eq!(c.resume(), Ok(State::Output(6)));
+        assert_eq!(c.resume(), Ok(State::NeedsInput));
+        c.push_input(0);
+        assert_eq!(c.resume(), Ok(State::Halted));
+        assert_eq!(seen.drain(), vec![20, 40, 60]);
+        // Outputs went to the sink rather than the buffer.
+        assert_eq!(c.drain_outputs(), vec![]);
+    }
+
+    #[test]
+    fn queues() {
+        let inputs = Queue::new();
+        let outputs = Queue::new();
+        let mut c = doubler()
+            .with_input(inputs.clone())
+            .with_output(outputs.clone());
+        inputs.push(5);
+        inputs.push(7);
+        assert_eq!(c.resume(), Ok(State::Output(10)));
+        assert_eq!(c.resume(), Ok(State::Output(14)));
+        assert_eq!(c.resume(), Ok(State::NeedsInput));
+        inputs.push(0);
+        assert_eq!(c.resume(), Ok(State::Halted));
+        assert!(inputs.is_empty());
+        assert_eq!(outputs.drain(), vec![10, 14]);
+    }
+
+    #[test]
+    fn channels_between_computers() {
+        let (to_first, first_input) = channel();
+        let (first_output, second_input) = channel();
+        let (second_output, results) = channel();
+        let first = thread::spawn(move || {
+            doubler()
+                .with_input(first_input)
+                .with_output(first_output)
+                .run_to_halt(vec![])
+        });
+        let second = thread::spawn(move || {
+            doubler()
+                .with_input(second_input)
+                .with_output(second_output)
+                .run_to_halt(vec![])
+        });
+        for value in &[1, 2, 3, 0] {
+            to_first.send(*value).unwrap();
+        }
+        assert_eq!(first.join().unwrap(), Ok(vec![]));
+        // The first computer halts on 0 without passing it on, so the second one runs out of
+        // input when the channel is disconnected.
+        assert!(second.join().unwrap().is_err());
+        assert_eq!(results.iter().collect::<Vec<i64>>(), vec![4, 8, 12]);
+    }
+}
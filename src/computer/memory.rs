@@ -0,0 +1,155 @@
+use super::word::Word;
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+// Number of words in a page of SparseMemory.
+const PAGE_SIZE: usize = 1024;
+
+// Backing store for the memory of a Computer. Words that were never written read as zero.
+pub trait Memory: Clone + Debug + PartialEq + Eq + Send + 'static {
+    type Word: Word;
+
+    fn get(&self, address: usize) -> Self::Word;
+    // Read of an operand by an executing instruction. Unlike get, it may have side effects, like
+    // a memory mapped device reacting to being read. Computer only loads the operands of an
+    // instruction once their addresses and its write target are known to be valid, but the
+    // instruction can still fail on the loaded values, e.g. when they overflow.
+    fn load(&self, address: usize) -> Self::Word {
+        self.get(address)
+    }
+    // Whether a write to address keeps the number of stored words within limit.
+    fn fits(&self, address: usize, limit: usize) -> bool;
+    fn set(&mut self, address: usize, value: Self::Word);
+    // Whether address is backed by a device rather than by stored words, so that a write to it
+    // can't be undone by writing the old value back.
+    fn is_device(&self, _address: usize) -> bool {
+        false
+    }
+    // Number of words stored.
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Dense memory, growing to the highest written address.
+impl<W: Word> Memory for Vec<W> {
+    type Word = W;
+
+    fn get(&self, address: usize) -> W {
+        self.as_slice()
+            .get(address)
+            .copied()
+            .unwrap_or_else(|| W::from_i64(0))
+    }
+    fn fits(&self, address: usize, limit: usize) -> bool {
+        address < Vec::len(self) || address < limit
+    }
+    fn set(&mut self, address: usize, value: W) {
+        if address >= Vec::len(self) {
+            self.resize(address + 1, W::from_i64(0));
+        }
+        self[address] = value;
+    }
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+}
+
+// Memory stored in pages that are allocated on first write, for programs using far apart
+// addresses, e.g. scratch space at 10^12.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseMemory<W> {
+    pages: HashMap<usize, Vec<W>>,
+}
+
+impl<W: Word> SparseMemory<W> {
+    pub fn new() -> Self {
+        Self {
+            pages: HashMap::new(),
+        }
+    }
+
+    pub fn from_words(program: &[W]) -> Self {
+        let mut mem = Self::new();
+        for (address, value) in program.iter().enumerate() {
+            mem.set(address, *value);
+        }
+        mem
+    }
+}
+
+impl<W: Word> Default for SparseMemory<W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W: Word> Memory for SparseMemory<W> {
+    type Word = W;
+
+    fn get(&self, address: usize) -> W {
+        self.pages
+            .get(&(address / PAGE_SIZE))
+            .map_or_else(|| W::from_i64(0), |page| page[address % PAGE_SIZE])
+    }
+    fn fits(&self, address: usize, limit: usize) -> bool {
+        self.pages.contains_key(&(address / PAGE_SIZE)) || self.len() + PAGE_SIZE <= limit
+    }
+    fn set(&mut self, address: usize, value: W) {
+        self.pages
+            .entry(address / PAGE_SIZE)
+            .or_insert_with(|| vec![W::from_i64(0); PAGE_SIZE])[address % PAGE_SIZE] = value;
+    }
+    fn len(&self) -> usize {
+        self.pages.len() * PAGE_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Computer;
+    use super::*;
+
+    // Stores 42 at address, then outputs the content of address.
+    fn scratch(address: i64) -> Vec<i64> {
+        vec![1101, 0, 42, address, 4, address, 99]
+    }
+
+    #[test]
+    fn far_addresses() {
+        let address = 1_000_000_000_000;
+        let mut c = Computer::from_memory(SparseMemory::from_words(&scratch(address)));
+        assert_eq!(c.run_to_halt(vec![]), Ok(vec![42]));
+        assert_eq!(c.mem().len(), 2 * PAGE_SIZE);
+        assert_eq!(c.peek(address as usize), 42);
+        assert_eq!(c.peek(address as usize + 1), 0);
+        // The dense backend would need terabytes.
+        let mut c = Computer::new(scratch(address));
+        assert!(c.run_to_halt(vec![]).is_err());
+    }
+
+    #[test]
+    fn backends_agree() {
+        let program = scratch(5000);
+        let mut dense = Computer::new(program.clone());
+        let mut sparse = Computer::from_memory(SparseMemory::from_words(&program));
+        assert_eq!(
+            dense.run_to_halt(vec![]).unwrap(),
+            sparse.run_to_halt(vec![]).unwrap()
+        );
+        for address in 0..6000 {
+            assert_eq!(dense.peek(address), sparse.peek(address));
+        }
+    }
+
+    #[test]
+    fn memory_limit() {
+        let mut c = Computer::from_memory(SparseMemory::from_words(&scratch(1 << 40)))
+            .with_memory_limit(PAGE_SIZE);
+        assert!(c.run_to_halt(vec![]).is_err());
+        let mut c = Computer::from_memory(SparseMemory::from_words(&scratch(1 << 40)))
+            .with_memory_limit(2 * PAGE_SIZE);
+        assert_eq!(c.run_to_halt(vec![]), Ok(vec![42]));
+    }
+}
@@ -0,0 +1,1411 @@
+use std::collections::VecDeque;
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use self::decode_cache::DecodeCache;
+use self::history::{History, Undo};
+use self::io::{InputSource, OutputSink};
+use self::memory::Memory;
+use self::profiler::Profile;
+use self::snapshot::Snapshot;
+use self::word::{clamp, Word};
+
+pub mod ascii;
+pub mod assembler;
+pub mod bus;
+pub mod decode_cache;
+pub mod disassembler;
+pub mod fuzz;
+pub mod history;
+pub mod io;
+pub mod memory;
+pub mod network;
+pub mod profiler;
+pub mod program;
+pub mod rng;
+pub mod snapshot;
+pub mod symbolic;
+pub mod topology;
+pub mod word;
+
+const PARAM_MODE_POSITION: usize = 0;
+const PARAM_MODE_IMMEDIATE: usize = 1;
+const PARAM_MODE_RELATIVE: usize = 2;
+
+// Default upper bound on the number of memory words a program may use, 128 MiB worth of i64.
+const DEFAULT_MEMORY_LIMIT: usize = 1 << 24;
+
+// Reading the clock every step would slow execution down noticeably, so the deadline is only
+// checked every this many steps.
+const DEADLINE_CHECK_INTERVAL: u64 = 1024;
+
+// Mnemonic and number of parameters for every opcode.
+const INSTRUCTIONS: [(i64, &str, usize); 10] = [
+    (1, "add", 3),
+    (2, "mul", 3),
+    (3, "in", 1),
+    (4, "out", 1),
+    (5, "jnz", 2),
+    (6, "jz", 2),
+    (7, "lt", 3),
+    (8, "eq", 3),
+    (9, "arb", 1),
+    (99, "hlt", 0),
+];
+
+// INSTRUCTIONS indexed by opcode, as every executed instruction is looked up.
+const INSTRUCTION_TABLE: [Option<(&str, usize)>; 100] = {
+    let mut table = [None; 100];
+    let mut i = 0;
+    while i < INSTRUCTIONS.len() {
+        let (opcode, mnemonic, params) = INSTRUCTIONS[i];
+        table[opcode as usize] = Some((mnemonic, params));
+        i += 1;
+    }
+    table
+};
+
+fn instruction_info(opcode: i64) -> Option<(&'static str, usize)> {
+    usize::try_from(opcode)
+        .ok()
+        .and_then(|opcode| INSTRUCTION_TABLE.get(opcode).copied().flatten())
+}
+
+// Divisor that moves the mode digit of each parameter to the ones place.
+const PARAM_MODE_DIVISORS: [i64; 3] = [100, 1000, 10000];
+
+fn param_mode(instruction: i64, index: usize) -> i64 {
+    (instruction / PARAM_MODE_DIVISORS[index]) % 10
+}
+
+#[derive(Debug, Clone, Copy)]
+enum OpCode<W> {
+    Add { a: W, b: W, r: usize },
+    Multiply { a: W, b: W, r: usize },
+    Input { r: usize },
+    Output { a: W },
+    // The destination is only checked to be an address when the jump is taken.
+    JumpIfTrue { a: W, d: W },
+    JumpIfFalse { a: W, d: W },
+    LessThan { a: W, b: W, r: usize },
+    Equals { a: W, b: W, r: usize },
+    AdjustRelativeBase { a: W },
+    Halt,
+}
+
+impl<W: Word> OpCode<W> {
+    // Variant name, for profiling.
+    fn name(&self) -> &'static str {
+        match self {
+            OpCode::Add { .. } => "Add",
+            OpCode::Multiply { .. } => "Multiply",
+            OpCode::Input { .. } => "Input",
+            OpCode::Output { .. } => "Output",
+            OpCode::JumpIfTrue { .. } => "JumpIfTrue",
+            OpCode::JumpIfFalse { .. } => "JumpIfFalse",
+            OpCode::LessThan { .. } => "LessThan",
+            OpCode::Equals { .. } => "Equals",
+            OpCode::AdjustRelativeBase { .. } => "AdjustRelativeBase",
+            OpCode::Halt => "Halt",
+        }
+    }
+
+    // Address the instruction writes to, if any.
+    fn target(&self) -> Option<usize> {
+        match *self {
+            OpCode::Add { r, .. }
+            | OpCode::Multiply { r, .. }
+            | OpCode::Input { r }
+            | OpCode::LessThan { r, .. }
+            | OpCode::Equals { r, .. } => Some(r),
+            _ => None,
+        }
+    }
+
+    // Variant name and resolved operand values, for tracing.
+    fn fields(&self) -> (&'static str, Vec<(&'static str, String)>) {
+        let operands: Vec<(&'static str, String)> = match *self {
+            OpCode::Add { a, b, r }
+            | OpCode::Multiply { a, b, r }
+            | OpCode::LessThan { a, b, r }
+            | OpCode::Equals { a, b, r } => vec![
+                ("a", a.to_string()),
+                ("b", b.to_string()),
+                ("r", r.to_string()),
+            ],
+            OpCode::Input { r } => vec![("r", r.to_string())],
+            OpCode::Output { a } | OpCode::AdjustRelativeBase { a } => vec![("a", a.to_string())],
+            OpCode::JumpIfTrue { a, d } | OpCode::JumpIfFalse { a, d } => {
+                vec![("a", a.to_string()), ("d", d.to_string())]
+            }
+            OpCode::Halt => vec![],
+        };
+        (self.name(), operands)
+    }
+}
+
+// Opcode and validated parameter modes of an instruction word. Kept small since a DecodeCache
+// holds one per address.
+#[derive(Debug, Clone, Copy)]
+struct Decoded {
+    opcode: u8,
+    size: u8,
+    modes: [u8; 3],
+}
+
+// Why an instruction word doesn't decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecodeError {
+    IllegalOpcode,
+    IllegalMode(i64),
+}
+
+fn decode(instruction: i64) -> Result<Decoded, DecodeError> {
+    let opcode = instruction % 100;
+    let (_, count) = instruction_info(opcode).ok_or(DecodeError::IllegalOpcode)?;
+    let mut decoded = Decoded {
+        opcode: opcode as u8,
+        size: count as u8 + 1,
+        modes: [PARAM_MODE_POSITION as u8; 3],
+    };
+    // Peels off one mode digit at a time, which is cheaper than a division per parameter.
+    let mut modes = instruction / 100;
+    for i in 0..count {
+        let mode = modes % 10;
+        modes /= 10;
+        match mode as usize {
+            PARAM_MODE_POSITION | PARAM_MODE_IMMEDIATE | PARAM_MODE_RELATIVE => {
+                decoded.modes[i] = mode as u8
+            }
+            _ => return Err(DecodeError::IllegalMode(mode)),
+        }
+    }
+    Ok(decoded)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VmError<W: Word = i64, M: Memory<Word = W> = Vec<W>> {
+    IllegalOpcode {
+        pc: usize,
+        instruction: i64,
+    },
+    IllegalMode {
+        pc: usize,
+        instruction: i64,
+        mode: i64,
+    },
+    NegativeAddress {
+        pc: usize,
+        instruction: i64,
+        address: i64,
+    },
+    OutOfBounds {
+        pc: usize,
+        instruction: i64,
+        address: usize,
+    },
+    InputExhausted {
+        pc: usize,
+        instruction: i64,
+    },
+    TraceFailed {
+        pc: usize,
+        instruction: i64,
+        message: String,
+    },
+    Overflow {
+        pc: usize,
+        instruction: i64,
+    },
+    // The step limit was reached before executing the instruction at pc. The state of the
+    // Computer at that point can be inspected by restoring the snapshot.
+    StepLimit {
+        pc: usize,
+        steps: u64,
+        state: Box<Snapshot<W, M>>,
+    },
+    // Like StepLimit, for the deadline.
+    Timeout {
+        pc: usize,
+        steps: u64,
+        state: Box<Snapshot<W, M>>,
+    },
+}
+
+impl<W: Word, M: Memory<Word = W>> fmt::Display for VmError<W, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VmError::IllegalOpcode { pc, instruction } => {
+                write!(
+                    f,
+                    "Illegal opcode in instruction {} at pc {}",
+                    instruction, pc
+                )
+            }
+            VmError::IllegalMode {
+                pc,
+                instruction,
+                mode,
+            } => write!(
+                f,
+                "Illegal param mode {} in instruction {} at pc {}",
+                mode, instruction, pc
+            ),
+            VmError::NegativeAddress {
+                pc,
+                instruction,
+                address,
+            } => write!(
+                f,
+                "Negative address {} in instruction {} at pc {}",
+                address, instruction, pc
+            ),
+            VmError::OutOfBounds {
+                pc,
+                instruction,
+                address,
+            } => write!(
+                f,
+                "Address {} exceeds memory limit in instruction {} at pc {}",
+                address, instruction, pc
+            ),
+            VmError::InputExhausted { pc, instruction } => write!(
+                f,
+                "No input left for instruction {} at pc {}",
+                instruction, pc
+            ),
+            VmError::TraceFailed {
+                pc,
+                instruction,
+                message,
+            } => write!(
+                f,
+                "Writing trace of instruction {} at pc {} failed: {}",
+                instruction, pc, message
+            ),
+            VmError::Overflow { pc, instruction } => write!(
+                f,
+                "Arithmetic overflow in instruction {} at pc {}",
+                instruction, pc
+            ),
+            VmError::StepLimit { pc, steps, .. } => {
+                write!(f, "Step limit reached after {} steps at pc {}", steps, pc)
+            }
+            VmError::Timeout { pc, steps, .. } => {
+                write!(f, "Deadline passed after {} steps at pc {}", steps, pc)
+            }
+        }
+    }
+}
+
+impl<W: Word, M: Memory<Word = W>> Error for VmError<W, M> {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State<W = i64> {
+    Halted,
+    NeedsInput,
+    Output(W),
+}
+
+// What Add, Multiply and AdjustRelativeBase do when the result doesn't fit in the word type. The
+// same policy applies in debug and release builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    // Two's complement wrap around.
+    Wrap,
+    // Clamp to the smallest or largest value of the word type.
+    Saturate,
+    // Stop with VmError::Overflow.
+    Error,
+}
+
+impl fmt::Display for OverflowPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OverflowPolicy::Wrap => write!(f, "wrap"),
+            OverflowPolicy::Saturate => write!(f, "saturate"),
+            OverflowPolicy::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(OverflowPolicy::Wrap),
+            "saturate" => Ok(OverflowPolicy::Saturate),
+            "error" => Ok(OverflowPolicy::Error),
+            _ => Err(format!("Unknown overflow policy '{}'", s)),
+        }
+    }
+}
+
+pub struct Computer<W: Word = i64, M: Memory<Word = W> = Vec<W>> {
+    pc: usize,
+    relative_base: W,
+    mem: M,
+    memory_limit: usize,
+    overflow: OverflowPolicy,
+    inputs: VecDeque<W>,
+    // Last emitted value, kept after the output buffer is drained.
+    output: W,
+    outputs: Vec<W>,
+    // Address written by the most recently executed instruction.
+    last_write: Option<usize>,
+    steps: u64,
+    // Set once a Halt instruction executed, so that further steps don't execute it again.
+    halted: bool,
+    step_limit: Option<u64>,
+    deadline: Option<Instant>,
+    tracer: Option<Box<dyn Write + Send>>,
+    profile: Option<Profile>,
+    history: Option<History<W>>,
+    decode_cache: Option<DecodeCache<W>>,
+    input_source: Option<Box<dyn InputSource<W>>>,
+    output_sink: Option<Box<dyn OutputSink<W>>>,
+}
+
+impl Computer {
+    pub fn new(mem: Vec<i64>) -> Self {
+        Self::from_words(mem)
+    }
+}
+
+impl<W: Word> Computer<W> {
+    // Creates a Computer with any word type, e.g. Computer::<i128>::from_words(widen(&program)).
+    pub fn from_words(mem: Vec<W>) -> Self {
+        Self::from_memory(mem)
+    }
+}
+
+impl<W: Word, M: Memory<Word = W>> Computer<W, M> {
+    // Creates a Computer with any memory backend, e.g.
+    // Computer::from_memory(SparseMemory::from_words(&program)).
+    pub fn from_memory(mem: M) -> Self {
+        Self {
+            pc: 0,
+            relative_base: W::from_i64(0),
+            mem,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            overflow: OverflowPolicy::Error,
+            inputs: VecDeque::new(),
+            output: W::from_i64(0),
+            outputs: vec![],
+            last_write: None,
+            steps: 0,
+            halted: false,
+            step_limit: None,
+            deadline: None,
+            tracer: None,
+            profile: None,
+            history: None,
+            decode_cache: None,
+            input_source: None,
+            output_sink: None,
+        }
+    }
+
+    // Limits how far memory may grow by writes past the end of the loaded program.
+    pub fn with_memory_limit(mut self, words: usize) -> Self {
+        self.memory_limit = words;
+        self
+    }
+
+    // Sets what happens when Add, Multiply or AdjustRelativeBase overflow. The default is
+    // OverflowPolicy::Error.
+    pub fn with_overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.overflow = policy;
+        self
+    }
+
+    // Stops with VmError::StepLimit instead of executing more than steps instructions in total.
+    pub fn with_step_limit(mut self, steps: u64) -> Self {
+        self.step_limit = Some(steps);
+        self
+    }
+
+    // Stops with VmError::Timeout once deadline has passed. The clock is only read every
+    // DEADLINE_CHECK_INTERVAL steps, and not while waiting for input.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    // Like with_deadline, with the deadline timeout from now.
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    // Looks up how instructions decode in cache before decoding them, e.g.
+    // Computer::new(program.clone()).with_decode_cache(DecodeCache::new(&program)).
+    // Building the cache takes about as long as decoding the whole program once, so it pays off
+    // when it is shared by many Computers or when instructions run many times.
+    pub fn with_decode_cache(mut self, cache: DecodeCache<W>) -> Self {
+        self.decode_cache = Some(cache);
+        self
+    }
+
+    // Logs every executed instruction to out as a line of JSON, e.g.
+    // {"step":0,"pc":0,"op":"Add","operands":{"a":1,"b":2,"r":5},
+    //  "writes":[{"address":5,"value":3}]}
+    // but all on one line.
+    pub fn with_tracer(mut self, out: impl Write + Send + 'static) -> Self {
+        self.tracer = Some(Box::new(out));
+        self
+    }
+
+    // Inputs are taken from source once the input queue is empty.
+    pub fn with_input(mut self, source: impl InputSource<W> + 'static) -> Self {
+        self.input_source = Some(Box::new(source));
+        self
+    }
+
+    // Outputs are sent to sink instead of the output buffer.
+    pub fn with_output(mut self, sink: impl OutputSink<W> + 'static) -> Self {
+        self.output_sink = Some(Box::new(sink));
+        self
+    }
+
+    // Memory beyond the loaded program reads as zero.
+    fn read(&self, address: usize) -> W {
+        self.mem.get(address)
+    }
+
+    // Current instruction as reported in errors.
+    fn instruction(&self) -> i64 {
+        clamp(self.read(self.pc))
+    }
+
+    // Writes past the end of memory grow it, up to the memory limit.
+    fn write(&mut self, address: usize, value: W) -> Result<(), VmError<W, M>> {
+        self.check_writable(address)?;
+        self.mem.set(address, value);
+        self.last_write = Some(address);
+        Ok(())
+    }
+
+    fn check_writable(&self, address: usize) -> Result<(), VmError<W, M>> {
+        if !self.mem.fits(address, self.memory_limit) {
+            return Err(VmError::OutOfBounds {
+                pc: self.pc,
+                instruction: self.instruction(),
+                address,
+            });
+        }
+        Ok(())
+    }
+
+    fn address(&self, value: W) -> Result<usize, VmError<W, M>> {
+        if value < W::from_i64(0) {
+            return Err(VmError::NegativeAddress {
+                pc: self.pc,
+                instruction: self.instruction(),
+                address: clamp(value),
+            });
+        }
+        // Any address too large for usize is beyond the memory limit as well.
+        value
+            .to_i64()
+            .and_then(|address| address.try_into().ok())
+            .ok_or_else(|| VmError::OutOfBounds {
+                pc: self.pc,
+                instruction: self.instruction(),
+                address: usize::MAX,
+            })
+    }
+
+    fn predecode(&self) -> Result<Decoded, VmError<W, M>> {
+        // No opcode is encoded by a word beyond the i64 range.
+        let instruction = self
+            .read(self.pc)
+            .to_i64()
+            .ok_or_else(|| VmError::IllegalOpcode {
+                pc: self.pc,
+                instruction: self.instruction(),
+            })?;
+        decode(instruction).map_err(|e| match e {
+            DecodeError::IllegalOpcode => VmError::IllegalOpcode {
+                pc: self.pc,
+                instruction,
+            },
+            DecodeError::IllegalMode(mode) => VmError::IllegalMode {
+                pc: self.pc,
+                instruction,
+                mode,
+            },
+        })
+    }
+
+    fn decoded(&self) -> Result<Decoded, VmError<W, M>> {
+        let cached = self
+            .decode_cache
+            .as_ref()
+            .and_then(|cache| cache.get(self.pc, self.read(self.pc)));
+        match cached {
+            Some(decoded) => Ok(decoded),
+            None => self.predecode(),
+        }
+    }
+
+    // Loads may have side effects on memory mapped devices. So every operand address is resolved
+    // and the write target checked before anything is loaded, and an instruction that fails to
+    // decode leaves devices alone. Errors that depend on the loaded values, like an overflow,
+    // still come after the loads.
+    fn decode_instruction(&mut self) -> Result<(OpCode<W>, usize), VmError<W, M>> {
+        let decoded = self.decoded()?;
+        let target = match decoded.opcode {
+            1 | 2 | 7 | 8 => Some(2),
+            3 => Some(0),
+            _ => None,
+        };
+        let mut sources = [None; 3];
+        let params = decoded.size as usize - 1;
+        for (index, source) in sources.iter_mut().enumerate().take(params) {
+            if Some(index) != target {
+                *source = self.param_address(&decoded, index)?;
+            }
+        }
+        let r = match target {
+            Some(index) => {
+                let address = self.get_address(&decoded, index)?;
+                self.check_writable(address)?;
+                address
+            }
+            None => 0,
+        };
+        let param = |index: usize| match sources[index] {
+            Some(address) => self.mem.load(address),
+            None => self.read(self.pc + index + 1),
+        };
+        let opcode = match decoded.opcode {
+            1 => OpCode::Add {
+                a: param(0),
+                b: param(1),
+                r,
+            },
+            2 => OpCode::Multiply {
+                a: param(0),
+                b: param(1),
+                r,
+            },
+            3 => OpCode::Input { r },
+            4 => OpCode::Output { a: param(0) },
+            5 => OpCode::JumpIfTrue {
+                a: param(0),
+                d: param(1),
+            },
+            6 => OpCode::JumpIfFalse {
+                a: param(0),
+                d: param(1),
+            },
+            7 => OpCode::LessThan {
+                a: param(0),
+                b: param(1),
+                r,
+            },
+            8 => OpCode::Equals {
+                a: param(0),
+                b: param(1),
+                r,
+            },
+            9 => OpCode::AdjustRelativeBase { a: param(0) },
+            99 => OpCode::Halt,
+            _ => unreachable!("predecode only accepts known opcodes"),
+        };
+        Ok((opcode, decoded.size as usize))
+    }
+    pub fn push_input(&mut self, value: W) {
+        self.inputs.push_back(value);
+    }
+
+    // Executes a single instruction. Returns the new state if the instruction halted, is waiting
+    // for input or produced an output. An instruction waiting for input is not executed, so the
+    // next step retries it. Once halted, steps return State::Halted without executing anything.
+    pub fn step(&mut self) -> Result<Option<State<W>>, VmError<W, M>> {
+        if self.halted {
+            return Ok(Some(State::Halted));
+        }
+        self.check_limits()?;
+        let pc_start = self.pc;
+        let started = self.profile.as_ref().map(|_| Instant::now());
+        self.last_write = None;
+        let (opcode, size) = self.decode_instruction()?;
+        let mut undo = self.history.as_ref().map(|_| Undo {
+            pc: pc_start,
+            relative_base: self.relative_base,
+            output: self.output,
+            outputs: self.outputs.len(),
+            write: opcode.target().map(|address| (address, self.read(address))),
+            input: None,
+        });
+        let mut state = None;
+        let mut jumped = false;
+        match opcode {
+            OpCode::Add { a, b, r } => {
+                let sum =
+                    self.arithmetic((a, b), W::checked_add, W::wrapping_add, W::saturating_add)?;
+                self.write(r, sum)?;
+            }
+            OpCode::Multiply { a, b, r } => {
+                let product =
+                    self.arithmetic((a, b), W::checked_mul, W::wrapping_mul, W::saturating_mul)?;
+                self.write(r, product)?;
+            }
+            OpCode::Input { r } => {
+                let input = match self.next_input() {
+                    Some(input) => input,
+                    None => return Ok(Some(State::NeedsInput)),
+                };
+                if let Some(undo) = &mut undo {
+                    undo.input = Some(input);
+                }
+                self.write(r, input)?;
+            }
+            OpCode::Output { a } => {
+                //println!("output: {}", a);
+                self.output = a;
+                match &mut self.output_sink {
+                    Some(sink) => sink.send_output(a),
+                    None => self.outputs.push(a),
+                }
+                state = Some(State::Output(a));
+            }
+            OpCode::JumpIfTrue { a, d } => {
+                if a != W::from_i64(0) {
+                    self.pc = self.address(d)?;
+                    jumped = true;
+                }
+            }
+            OpCode::JumpIfFalse { a, d } => {
+                if a == W::from_i64(0) {
+                    self.pc = self.address(d)?;
+                    jumped = true;
+                }
+            }
+            OpCode::LessThan { a, b, r } => {
+                self.write(r, W::from_i64(if a < b { 1 } else { 0 }))?;
+            }
+            OpCode::Equals { a, b, r } => {
+                self.write(r, W::from_i64(if a == b { 1 } else { 0 }))?;
+            }
+            OpCode::AdjustRelativeBase { a } => {
+                self.relative_base = self.arithmetic(
+                    (self.relative_base, a),
+                    W::checked_add,
+                    W::wrapping_add,
+                    W::saturating_add,
+                )?;
+            }
+            OpCode::Halt => {
+                self.halted = true;
+                state = Some(State::Halted);
+            }
+        }
+        // Don't increment PC for jumps taken, even to the jump itself, or when halted.
+        if !jumped && state != Some(State::Halted) {
+            self.pc += size;
+        }
+        // The instruction has executed even if tracing it fails, so the error is returned only
+        // after it has been counted, profiled and recorded.
+        let traced = match self.tracer {
+            Some(_) => self.trace(pc_start, &opcode),
+            None => Ok(()),
+        };
+        if let (Some(profile), Some(started)) = (&mut self.profile, started) {
+            let io = matches!(opcode, OpCode::Input { .. } | OpCode::Output { .. });
+            profile.record(pc_start, opcode.name(), io, started.elapsed());
+        }
+        if let (Some(history), Some(undo)) = (&mut self.history, undo) {
+            history.push(undo);
+        }
+        self.steps += 1;
+        traced?;
+        Ok(state)
+    }
+    fn check_limits(&self) -> Result<(), VmError<W, M>> {
+        if self.step_limit.map_or(false, |limit| self.steps >= limit) {
+            return Err(VmError::StepLimit {
+                pc: self.pc,
+                steps: self.steps,
+                state: Box::new(self.snapshot()),
+            });
+        }
+        if let Some(deadline) = self.deadline {
+            if self.steps % DEADLINE_CHECK_INTERVAL == 0 && Instant::now() >= deadline {
+                return Err(VmError::Timeout {
+                    pc: self.pc,
+                    steps: self.steps,
+                    state: Box::new(self.snapshot()),
+                });
+            }
+        }
+        Ok(())
+    }
+    // Applies the overflow policy to an operation given as its checked, wrapping and saturating
+    // variants.
+    fn arithmetic(
+        &self,
+        (a, b): (W, W),
+        checked: fn(W, W) -> Option<W>,
+        wrapping: fn(W, W) -> W,
+        saturating: fn(W, W) -> W,
+    ) -> Result<W, VmError<W, M>> {
+        match (checked(a, b), self.overflow) {
+            (Some(value), _) => Ok(value),
+            (None, OverflowPolicy::Wrap) => Ok(wrapping(a, b)),
+            (None, OverflowPolicy::Saturate) => Ok(saturating(a, b)),
+            (None, OverflowPolicy::Error) => Err(VmError::Overflow {
+                pc: self.pc,
+                instruction: self.instruction(),
+            }),
+        }
+    }
+    fn trace(&mut self, pc: usize, opcode: &OpCode<W>) -> Result<(), VmError<W, M>> {
+        let (name, operands) = opcode.fields();
+        let operands: Vec<String> = operands
+            .iter()
+            .map(|(field, value)| format!("\"{}\":{}", field, value))
+            .collect();
+        let writes = match self.last_write {
+            Some(address) => format!(
+                "{{\"address\":{},\"value\":{}}}",
+                address,
+                self.read(address)
+            ),
+            None => String::new(),
+        };
+        let line = format!(
+            "{{\"step\":{},\"pc\":{},\"op\":\"{}\",\"operands\":{{{}}},\"writes\":[{}]}}",
+            self.steps,
+            pc,
+            name,
+            operands.join(","),
+            writes
+        );
+        let tracer = self.tracer.as_mut().unwrap();
+        writeln!(tracer, "{}", line).map_err(|e| VmError::TraceFailed {
+            pc,
+            instruction: clamp(self.read(pc)),
+            message: e.to_string(),
+        })
+    }
+    // Runs until the program halts, needs more input or produces an output.
+    pub fn resume(&mut self) -> Result<State<W>, VmError<W, M>> {
+        loop {
+            if let Some(state) = self.step()? {
+                return Ok(state);
+            }
+        }
+    }
+    fn next_input(&mut self) -> Option<W> {
+        match (self.inputs.pop_front(), &mut self.input_source) {
+            (None, Some(source)) => source.next_input(),
+            (input, _) => input,
+        }
+    }
+    fn input_exhausted(&self) -> VmError<W, M> {
+        VmError::InputExhausted {
+            pc: self.pc,
+            instruction: self.instruction(),
+        }
+    }
+    pub fn run_program(&mut self, inputs: Vec<W>) -> W {
+        self.try_run_program(inputs)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+    // Appends inputs to the input queue and runs until halted.
+    pub fn try_run_program(&mut self, inputs: Vec<W>) -> Result<W, VmError<W, M>> {
+        self.inputs.extend(inputs);
+        loop {
+            match self.resume()? {
+                State::Halted => return Ok(self.output),
+                State::NeedsInput => return Err(self.input_exhausted()),
+                State::Output(_) => (),
+            }
+        }
+    }
+    // Appends inputs to the input queue, runs until halted and returns all buffered outputs.
+    pub fn run_to_halt(&mut self, inputs: Vec<W>) -> Result<Vec<W>, VmError<W, M>> {
+        self.inputs.extend(inputs);
+        loop {
+            match self.resume()? {
+                State::Halted => return Ok(self.drain_outputs()),
+                State::NeedsInput => return Err(self.input_exhausted()),
+                State::Output(_) => (),
+            }
+        }
+    }
+    // Returns (halted(bool), output)
+    pub fn run_until_output(&mut self, inputs: Vec<W>) -> (bool, W) {
+        self.try_run_until_output(inputs)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+    // Appends inputs to the input queue and runs until halted or an output is produced.
+    pub fn try_run_until_output(&mut self, inputs: Vec<W>) -> Result<(bool, W), VmError<W, M>> {
+        self.inputs.extend(inputs);
+        match self.resume()? {
+            State::Halted => Ok((true, self.output)),
+            State::Output(a) => Ok((false, a)),
+            State::NeedsInput => Err(self.input_exhausted()),
+        }
+    }
+    // Address the param is loaded from, or None if it is immediate.
+    fn param_address(
+        &self,
+        decoded: &Decoded,
+        index: usize,
+    ) -> Result<Option<usize>, VmError<W, M>> {
+        match decoded.modes[index] as usize {
+            PARAM_MODE_IMMEDIATE => Ok(None),
+            _ => self.get_address(decoded, index).map(Some),
+        }
+    }
+    // Write targets are addresses, so the param itself is wanted rather than its content.
+    fn get_address(&self, decoded: &Decoded, index: usize) -> Result<usize, VmError<W, M>> {
+        let val = self.read(self.pc + index + 1);
+        match decoded.modes[index] as usize {
+            PARAM_MODE_RELATIVE => self.relative_address(val),
+            _ => self.address(val),
+        }
+    }
+    // An offset that overflows the relative base can't be an address under any overflow policy.
+    fn relative_address(&self, offset: W) -> Result<usize, VmError<W, M>> {
+        match self.relative_base.checked_add(offset) {
+            Some(address) => self.address(address),
+            None => Err(VmError::Overflow {
+                pc: self.pc,
+                instruction: self.instruction(),
+            }),
+        }
+    }
+    // Takes every output emitted since the last drain, oldest first.
+    pub fn drain_outputs(&mut self) -> Vec<W> {
+        std::mem::take(&mut self.outputs)
+    }
+    pub fn mem(&self) -> &M {
+        &self.mem
+    }
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+    pub fn relative_base(&self) -> W {
+        self.relative_base
+    }
+    pub fn pending_inputs(&self) -> &VecDeque<W> {
+        &self.inputs
+    }
+    // Number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+    pub fn last_write(&self) -> Option<usize> {
+        self.last_write
+    }
+    pub fn peek(&self, address: usize) -> W {
+        self.read(address)
+    }
+    pub fn poke(&mut self, address: usize, value: W) -> Result<(), VmError<W, M>> {
+        self.write(address, value)
+    }
+}
+
+// Reads a number and outputs it doubled, until it reads 0. Used by the tests of the submodules.
+#[cfg(test)]
+const DOUBLER: [i64; 16] = [
+    3, 15, 1006, 15, 14, 1002, 15, 2, 15, 4, 15, 1105, 1, 0, 99, 0,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn input_output() {
+        let program = vec![3, 0, 4, 0, 99];
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(333)), 333);
+    }
+
+    #[test]
+    fn mul_immediate() {
+        let program = vec![1002, 7, 3, 0, 4, 0, 99, 33];
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(1)), 99);
+    }
+
+    #[test]
+    fn add_immediate() {
+        let program = vec![1101, 100, -5, 0, 4, 0, 99];
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(1)), 95);
+    }
+
+    #[test]
+    fn equal_to_8_position() {
+        let program = vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8];
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(8)), 1);
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(7)), 0);
+    }
+
+    #[test]
+    fn less_than_8_position() {
+        let program = vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8];
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(7)), 1);
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(8)), 0);
+    }
+
+    #[test]
+    fn equal_to_8_immediate() {
+        let program = vec![3, 3, 1108, -1, 8, 3, 4, 3, 99];
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(8)), 1);
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(7)), 0);
+    }
+
+    #[test]
+    fn less_than_8_immediate() {
+        let program = vec![3, 3, 1107, -1, 8, 3, 4, 3, 99];
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(7)), 1);
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(8)), 0);
+    }
+
+    #[test]
+    fn jump_if_true_position() {
+        let program = vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9];
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(0)), 0);
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(3)), 1);
+    }
+
+    #[test]
+    fn jump_if_true_immediate() {
+        let program = vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1];
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(0)), 0);
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(3)), 1);
+    }
+
+    #[test]
+    fn day5_larger_example() {
+        let program = vec![
+            3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0,
+            0, 1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4,
+            20, 1105, 1, 46, 98, 99,
+        ];
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(7)), 999);
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(8)), 1000);
+        assert_eq!(Computer::new(program.clone()).run_program(vec!(9)), 1001);
+    }
+
+    #[test]
+    fn day9_quine() {
+        let program = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let mut c = Computer::new(program.clone());
+        let mut outputs = vec![];
+        loop {
+            let (halted, output) = c.run_until_output(vec![]);
+            if halted {
+                break;
+            }
+            outputs.push(output);
+        }
+        assert_eq!(outputs, program);
+    }
+
+    #[test]
+    fn day9_16_digit_number() {
+        let program = vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0];
+        assert_eq!(
+            Computer::new(program).run_program(vec![]).to_string().len(),
+            16
+        );
+    }
+
+    #[test]
+    fn day9_large_number() {
+        let program = vec![104, 1125899906842624, 99];
+        assert_eq!(Computer::new(program).run_program(vec![]), 1125899906842624);
+    }
+
+    #[test]
+    fn relative_mode_write() {
+        // Set relative base to 8 and store input at rb-1 (address 7), then output it.
+        let program = vec![109, 8, 203, -1, 4, 7, 99, 0];
+        assert_eq!(Computer::new(program).run_program(vec![42]), 42);
+    }
+
+    #[test]
+    fn memory_grows_on_write() {
+        // Store input far beyond the program and output it, then output an untouched cell.
+        let program = vec![3, 1000, 4, 1000, 4, 500, 99];
+        let mut c = Computer::new(program);
+        assert_eq!(c.run_until_output(vec![7]), (false, 7));
+        assert_eq!(c.run_until_output(vec![]), (false, 0));
+        assert_eq!(c.mem().len(), 1001);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds memory limit")]
+    fn memory_limit() {
+        let program = vec![3, 1000, 99];
+        Computer::new(program)
+            .with_memory_limit(100)
+            .run_program(vec![1]);
+    }
+
+    #[test]
+    fn illegal_opcode() {
+        let program = vec![1, 0, 0, 0, 42];
+        assert_eq!(
+            Computer::new(program).try_run_program(vec![]),
+            Err(VmError::IllegalOpcode {
+                pc: 4,
+                instruction: 42
+            })
+        );
+    }
+
+    #[test]
+    fn illegal_mode() {
+        let program = vec![301, 0, 0, 0, 99];
+        assert_eq!(
+            Computer::new(program).try_run_program(vec![]),
+            Err(VmError::IllegalMode {
+                pc: 0,
+                instruction: 301,
+                mode: 3
+            })
+        );
+    }
+
+    #[test]
+    fn negative_address() {
+        let program = vec![4, -1, 99];
+        assert_eq!(
+            Computer::new(program).try_run_until_output(vec![]),
+            Err(VmError::NegativeAddress {
+                pc: 0,
+                instruction: 4,
+                address: -1
+            })
+        );
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let program = vec![3, 1000, 99];
+        assert_eq!(
+            Computer::new(program)
+                .with_memory_limit(100)
+                .try_run_program(vec![1]),
+            Err(VmError::OutOfBounds {
+                pc: 0,
+                instruction: 3,
+                address: 1000
+            })
+        );
+    }
+
+    #[test]
+    fn input_exhausted() {
+        let program = vec![3, 0, 3, 0, 99];
+        assert_eq!(
+            Computer::new(program).try_run_program(vec![1]),
+            Err(VmError::InputExhausted {
+                pc: 2,
+                instruction: 3
+            })
+        );
+    }
+
+    // Outputs a + b and a * b.
+    fn add_and_multiply(a: i64, b: i64) -> Vec<i64> {
+        vec![1101, a, b, 13, 1102, a, b, 14, 4, 13, 4, 14, 99, 0, 0]
+    }
+
+    #[test]
+    fn step_limit() {
+        let mut c = Computer::new(vec![1101, 1, 2, 5, 99, 0]).with_step_limit(2);
+        assert_eq!(c.run_to_halt(vec![]), Ok(vec![]));
+        // Adds 1 to address 7 forever.
+        let program = vec![1001, 7, 1, 7, 1105, 1, 0, 0];
+        let error = Computer::new(program)
+            .with_step_limit(11)
+            .try_run_program(vec![])
+            .unwrap_err();
+        let state = match &error {
+            VmError::StepLimit {
+                pc: 4,
+                steps: 11,
+                state,
+            } => state.restore(),
+            _ => panic!("Unexpected error {}", error),
+        };
+        assert_eq!(state.peek(7), 6);
+        assert_eq!(state.steps(), 11);
+        assert_eq!(
+            error.to_string(),
+            "Step limit reached after 11 steps at pc 4"
+        );
+    }
+
+    #[test]
+    fn timeout() {
+        let mut c =
+            Computer::new(vec![1105, 1, 3, 1105, 1, 0]).with_timeout(Duration::from_millis(10));
+        match c.try_run_program(vec![]) {
+            Err(VmError::Timeout { pc: 0, steps, .. }) => {
+                assert!(steps > 0);
+                assert_eq!(steps % DEADLINE_CHECK_INTERVAL, 0);
+            }
+            result => panic!("Unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn jump_to_itself() {
+        let mut c = Computer::new(vec![1105, 1, 0]).with_step_limit(3);
+        assert!(matches!(
+            c.resume(),
+            Err(VmError::StepLimit {
+                pc: 0,
+                steps: 3,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn jump_destinations() {
+        // The destination of a jump that isn't taken doesn't need to be an address.
+        assert_eq!(
+            Computer::new(vec![1106, 1, -1, 99]).resume(),
+            Ok(State::Halted)
+        );
+        assert_eq!(
+            Computer::new(vec![1105, 1, -1, 99]).resume(),
+            Err(VmError::NegativeAddress {
+                pc: 0,
+                instruction: 1105,
+                address: -1
+            })
+        );
+    }
+
+    #[test]
+    fn relative_base_overflow() {
+        let mut c = Computer::new(vec![109, i64::MAX, 204, 1, 99]);
+        assert_eq!(
+            c.resume(),
+            Err(VmError::Overflow {
+                pc: 2,
+                instruction: 204
+            })
+        );
+        let mut c = Computer::new(vec![109, i64::MAX, 109, 1, 4, 1, 99])
+            .with_overflow(OverflowPolicy::Wrap);
+        assert_eq!(c.resume(), Ok(State::Output(i64::MAX)));
+        assert_eq!(c.relative_base(), i64::MIN);
+    }
+
+    #[test]
+    fn self_modifying_code() {
+        for cached in &[true, false] {
+            let computer = |program: Vec<i64>| {
+                let cache = DecodeCache::new(&program);
+                let c = Computer::new(program);
+                if *cached {
+                    c.with_decode_cache(cache)
+                } else {
+                    c
+                }
+            };
+            // Outputs 5, then changes the output operand to 7 and loops.
+            let mut c = computer(vec![104, 5, 1101, 0, 7, 1, 1105, 1, 0]);
+            assert_eq!(c.resume(), Ok(State::Output(5)));
+            assert_eq!(c.resume(), Ok(State::Output(7)));
+            assert_eq!(c.resume(), Ok(State::Output(7)));
+            // Outputs 5, then replaces the output instruction by a halt and loops.
+            let mut c = computer(vec![104, 5, 1101, 0, 99, 0, 1105, 1, 0]);
+            assert_eq!(c.resume(), Ok(State::Output(5)));
+            assert_eq!(c.resume(), Ok(State::Halted));
+            // Outputs 5 and loops, until the jump target is poked to point at the halt.
+            let mut c = computer(vec![104, 5, 1105, 1, 0, 99]);
+            assert_eq!(c.resume(), Ok(State::Output(5)));
+            c.poke(4, 5).unwrap();
+            assert_eq!(c.resume(), Ok(State::Halted));
+        }
+    }
+
+    #[test]
+    fn overflow_wrap() {
+        let mut c =
+            Computer::new(add_and_multiply(i64::MAX, 2)).with_overflow(OverflowPolicy::Wrap);
+        assert_eq!(c.run_to_halt(vec![]), Ok(vec![i64::MIN + 1, -2]));
+    }
+
+    #[test]
+    fn overflow_saturate() {
+        let mut c =
+            Computer::new(add_and_multiply(i64::MIN, 2)).with_overflow(OverflowPolicy::Saturate);
+        assert_eq!(c.run_to_halt(vec![]), Ok(vec![i64::MIN + 2, i64::MIN]));
+        let mut c =
+            Computer::new(add_and_multiply(i64::MAX, 1)).with_overflow(OverflowPolicy::Saturate);
+        assert_eq!(c.run_to_halt(vec![]), Ok(vec![i64::MAX, i64::MAX]));
+    }
+
+    #[test]
+    fn overflow_error() {
+        assert_eq!(
+            Computer::new(add_and_multiply(i64::MAX, 1)).run_to_halt(vec![]),
+            Err(VmError::Overflow {
+                pc: 0,
+                instruction: 1101
+            })
+        );
+        assert_eq!(
+            Computer::new(add_and_multiply(1 << 32, 1 << 31)).run_to_halt(vec![]),
+            Err(VmError::Overflow {
+                pc: 4,
+                instruction: 1102
+            })
+        );
+        assert_eq!(
+            Computer::new(add_and_multiply(-3, 4)).run_to_halt(vec![]),
+            Ok(vec![1, -12])
+        );
+        assert_eq!("saturate".parse(), Ok(OverflowPolicy::Saturate));
+        assert_eq!(OverflowPolicy::Wrap.to_string(), "wrap");
+    }
+
+    #[test]
+    fn pause_on_input() {
+        // Outputs the sum of two inputs, then the first input again.
+        let program = vec![3, 13, 3, 14, 1, 13, 14, 15, 4, 15, 4, 13, 99, 0, 0, 0];
+        let mut c = Computer::new(program);
+        assert_eq!(c.resume(), Ok(State::NeedsInput));
+        c.push_input(5);
+        assert_eq!(c.resume(), Ok(State::NeedsInput));
+        assert_eq!(c.resume(), Ok(State::NeedsInput));
+        c.push_input(3);
+        assert_eq!(c.resume(), Ok(State::Output(8)));
+        assert_eq!(c.resume(), Ok(State::Output(5)));
+        assert_eq!(c.resume(), Ok(State::Halted));
+    }
+
+    #[test]
+    fn step_until_halted() {
+        let program = vec![1101, 2, 3, 5, 4, 0, 99];
+        let mut c = Computer::new(program);
+        assert_eq!(c.step(), Ok(None));
+        assert_eq!(c.step(), Ok(Some(State::Output(5))));
+        assert_eq!(c.step(), Ok(Some(State::Halted)));
+        assert_eq!(c.step(), Ok(Some(State::Halted)));
+        assert_eq!(c.steps(), 3);
+    }
+
+    #[test]
+    fn collect_all_outputs() {
+        let program = vec![104, 0, 104, 0, 3, 9, 4, 9, 99, 0];
+        let mut c = Computer::new(program);
+        assert_eq!(c.run_to_halt(vec![7]), Ok(vec![0, 0, 7]));
+        assert_eq!(c.drain_outputs(), vec![]);
+    }
+
+    #[test]
+    fn drain_outputs() {
+        let program = vec![104, 1, 104, 2, 3, 11, 104, 3, 4, 11, 99, 0];
+        let mut c = Computer::new(program);
+        assert_eq!(c.resume(), Ok(State::Output(1)));
+        assert_eq!(c.resume(), Ok(State::Output(2)));
+        assert_eq!(c.resume(), Ok(State::NeedsInput));
+        assert_eq!(c.drain_outputs(), vec![1, 2]);
+        assert_eq!(c.run_to_halt(vec![4]), Ok(vec![3, 4]));
+    }
+
+    #[test]
+    fn last_write() {
+        let program = vec![1101, 2, 3, 9, 4, 9, 3, 10, 99];
+        let mut c = Computer::new(program);
+        c.step().unwrap();
+        assert_eq!(c.last_write(), Some(9));
+        c.step().unwrap();
+        assert_eq!(c.last_write(), None);
+        assert_eq!(c.step(), Ok(Some(State::NeedsInput)));
+        assert_eq!(c.last_write(), None);
+        c.push_input(7);
+        c.step().unwrap();
+        assert_eq!(c.last_write(), Some(10));
+        assert_eq!(c.peek(10), 7);
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace() {
+        let program = vec![1101, 2, 3, 7, 4, 7, 99, 0];
+        let buffer = SharedBuffer::default();
+        let mut c = Computer::new(program).with_tracer(buffer.clone());
+        assert_eq!(c.run_to_halt(vec![]), Ok(vec![5]));
+        let trace = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            trace.lines().collect::<Vec<&str>>(),
+            vec![
+                concat!(
+                    r#"{"step":0,"pc":0,"op":"Add","operands":{"a":2,"b":3,"r":7},"#,
+                    r#""writes":[{"address":7,"value":5}]}"#
+                ),
+                r#"{"step":1,"pc":4,"op":"Output","operands":{"a":5},"writes":[]}"#,
+                r#"{"step":2,"pc":6,"op":"Halt","operands":{},"writes":[]}"#,
+            ]
+        );
+        assert_eq!(c.steps(), 3);
+        // Steps after halting aren't traced.
+        assert_eq!(c.step(), Ok(Some(State::Halted)));
+        assert_eq!(buffer.0.lock().unwrap().len(), trace.len());
+        assert_eq!(c.steps(), 3);
+    }
+
+    struct BrokenPipe;
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn failed_trace() {
+        let mut c = Computer::new(vec![1101, 1, 2, 5, 99])
+            .with_tracer(BrokenPipe)
+            .with_history(10);
+        assert!(matches!(
+            c.step(),
+            Err(VmError::TraceFailed {
+                pc: 0,
+                instruction: 1101,
+                ..
+            })
+        ));
+        // The instruction still executed, and is accounted for like any other.
+        assert_eq!((c.pc(), c.peek(5)), (4, 3));
+        assert_eq!(c.steps(), 1);
+        assert_eq!(c.history_len(), 1);
+        assert!(c.step_back());
+        assert_eq!((c.pc(), c.peek(5)), (0, 0));
+    }
+}
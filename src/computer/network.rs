@@ -0,0 +1,460 @@
+use super::{Computer, State, VmError};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_NAT_ADDRESS: i64 = 255;
+// Number of consecutive empty input polls after which a node counts as idle.
+const IDLE_POLLS: usize = 2;
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+// Number of instructions a node executes between checks whether the network has stopped.
+const STOP_CHECK_INTERVAL: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+    pub source: usize,
+    pub destination: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    // Packet delivered to the input queue of another node.
+    Sent(Packet),
+    // Packet received and stored by the NAT.
+    Nat(Packet),
+    // The network was idle, so the NAT sent its last packet on to node 0.
+    Wakeup(Packet),
+    // Packet to an address without a node, or to a node that has stopped. It is dropped.
+    Undeliverable(Packet),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkError {
+    pub address: usize,
+    pub error: VmError,
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Node {} failed: {}", self.address, self.error)
+    }
+}
+
+impl Error for NetworkError {}
+
+enum Message {
+    Packet(Packet),
+    Failed(usize, VmError),
+}
+
+struct Node {
+    packets: Sender<(i64, i64)>,
+    idle: Arc<AtomicUsize>,
+}
+
+// A network of Computers running the same program, each on its own thread, in the style of the
+// day 23 category six network. Every node first gets its address as input. It then sends packets
+// by outputting destination, x and y, and receives packets as x followed by y. A node asking for
+// input gets -1 if no packet arrives within a millisecond.
+//
+// Packets to the NAT address are kept by the NAT. When every node has polled for input without
+// getting any or has stopped, and no packets are in flight, the network is idle and the NAT sends
+// its last packet to node 0. Idle detection assumes nodes only send packets in response to
+// received ones.
+pub struct Network {
+    program: Vec<i64>,
+    size: usize,
+    nat_address: i64,
+}
+
+impl Network {
+    pub fn new(program: Vec<i64>, size: usize) -> Self {
+        Self {
+            program,
+            size,
+            nat_address: DEFAULT_NAT_ADDRESS,
+        }
+    }
+
+    pub fn with_nat_address(mut self, address: i64) -> Self {
+        self.nat_address = address;
+        self
+    }
+
+    // Runs the network, passing every routing event to on_event until it returns false. Also
+    // stops when all nodes have halted, or when the network is idle and the NAT has nothing to
+    // send or node 0 has stopped. Every node is stopped before returning, including nodes that
+    // loop without asking for input.
+    pub fn run(&self, on_event: impl FnMut(&Event) -> bool) -> Result<(), NetworkError> {
+        let (router, messages) = channel();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut nodes = vec![];
+        let mut threads = vec![];
+        for address in 0..self.size {
+            let (packets, inbox) = channel();
+            let idle = Arc::new(AtomicUsize::new(0));
+            nodes.push(Node {
+                packets,
+                idle: idle.clone(),
+            });
+            let c = self.node(address, inbox, router.clone(), &in_flight, &idle, &stop);
+            let stop = stop.clone();
+            threads.push(thread::spawn(move || {
+                let (mut c, router) = c;
+                loop {
+                    if c.steps() % STOP_CHECK_INTERVAL == 0 && stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match c.step() {
+                        Ok(None) | Ok(Some(State::Output(_))) => (),
+                        Ok(Some(_)) => break,
+                        Err(e) => {
+                            let _ = router.send(Message::Failed(address, e));
+                            break;
+                        }
+                    }
+                }
+                // Dropping the Computer closes its inbox, so packets to it fail to send. A node
+                // that has stopped won't send any packets either.
+                drop(c);
+                idle.store(usize::MAX, Ordering::SeqCst);
+            }));
+        }
+        drop(router);
+        let result = self.route(&nodes, &messages, &in_flight, on_event);
+        stop.store(true, Ordering::SeqCst);
+        for t in threads {
+            t.join().unwrap();
+        }
+        result
+    }
+
+    // Creates the Computer for a node, wired to its inbox and the router.
+    fn node(
+        &self,
+        address: usize,
+        inbox: Receiver<(i64, i64)>,
+        router: Sender<Message>,
+        in_flight: &Arc<AtomicUsize>,
+        idle: &Arc<AtomicUsize>,
+        stop: &Arc<AtomicBool>,
+    ) -> (Computer, Sender<Message>) {
+        let (in_flight, idle, stop) = (in_flight.clone(), idle.clone(), stop.clone());
+        let mut pending_y = None;
+        let source = {
+            let (in_flight, idle) = (in_flight.clone(), idle.clone());
+            move || {
+                if let Some(y) = pending_y.take() {
+                    return Some(y);
+                }
+                if stop.load(Ordering::SeqCst) {
+                    return None;
+                }
+                match inbox.recv_timeout(POLL_INTERVAL) {
+                    Ok((x, y)) => {
+                        idle.store(0, Ordering::SeqCst);
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        pending_y = Some(y);
+                        Some(x)
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        idle.fetch_add(1, Ordering::SeqCst);
+                        Some(-1)
+                    }
+                    Err(RecvTimeoutError::Disconnected) => None,
+                }
+            }
+        };
+        let mut buffer = vec![];
+        let failures = router.clone();
+        let sink = move |value| {
+            buffer.push(value);
+            if buffer.len() == 3 {
+                in_flight.fetch_add(1, Ordering::SeqCst);
+                idle.store(0, Ordering::SeqCst);
+                let _ = router.send(Message::Packet(Packet {
+                    source: address,
+                    destination: buffer[0],
+                    x: buffer[1],
+                    y: buffer[2],
+                }));
+                buffer.clear();
+            }
+        };
+        let mut c = Computer::new(self.program.clone())
+            .with_input(source)
+            .with_output(sink);
+        c.push_input(address as i64);
+        (c, failures)
+    }
+
+    fn route(
+        &self,
+        nodes: &[Node],
+        messages: &Receiver<Message>,
+        in_flight: &AtomicUsize,
+        mut on_event: impl FnMut(&Event) -> bool,
+    ) -> Result<(), NetworkError> {
+        let mut nat = None;
+        loop {
+            let event = match messages.recv_timeout(POLL_INTERVAL) {
+                Ok(Message::Packet(packet)) => {
+                    let node = usize::try_from(packet.destination)
+                        .ok()
+                        .and_then(|d| nodes.get(d));
+                    if packet.destination == self.nat_address {
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        nat = Some(packet);
+                        Some(Event::Nat(packet))
+                    } else if let Some(node) = node {
+                        if node.packets.send((packet.x, packet.y)).is_ok() {
+                            Some(Event::Sent(packet))
+                        } else {
+                            in_flight.fetch_sub(1, Ordering::SeqCst);
+                            Some(Event::Undeliverable(packet))
+                        }
+                    } else {
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        Some(Event::Undeliverable(packet))
+                    }
+                }
+                Ok(Message::Failed(address, error)) => return Err(NetworkError { address, error }),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            };
+            if let Some(event) = event {
+                if !on_event(&event) {
+                    return Ok(());
+                }
+            }
+            let idle = in_flight.load(Ordering::SeqCst) == 0
+                && nodes
+                    .iter()
+                    .all(|n| n.idle.load(Ordering::SeqCst) >= IDLE_POLLS);
+            if idle {
+                match (nat, nodes.first()) {
+                    (Some(packet), Some(node)) => {
+                        in_flight.fetch_add(1, Ordering::SeqCst);
+                        if node.packets.send((packet.x, packet.y)).is_err() {
+                            in_flight.fetch_sub(1, Ordering::SeqCst);
+                            return Ok(());
+                        }
+                        if !on_event(&Event::Wakeup(packet)) {
+                            return Ok(());
+                        }
+                    }
+                    _ => return Ok(()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble;
+    use super::*;
+
+    // Nodes pass packets around a ring, incrementing y. Node 0 sends the first packet and the
+    // last node sends to the NAT.
+    fn ring(size: usize) -> Vec<i64> {
+        assemble(&format!(
+            "
+                in [address]
+                jnz [address], #receive
+                out #1
+                out #0
+                out #0
+            receive:
+                in [x]
+                eq [x], #-1, [tmp]
+                jnz [tmp], #receive
+                in [y]
+                add [y], #1, [y]
+                add [address], #1, [destination]
+                eq [destination], #{}, [tmp]
+                jz [tmp], #send
+                add #255, #0, [destination]
+            send:
+                out [destination]
+                out [x]
+                out [y]
+                jz #0, #receive
+            address: db 0
+            destination: db 0
+            x: db 0
+            y: db 0
+            tmp: db 0
+            ",
+            size
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn ring_with_nat() {
+        let mut events = vec![];
+        let result = Network::new(ring(20), 20).run(|event| {
+            events.push(*event);
+            events.iter().filter(|e| matches!(e, Event::Nat(_))).count() < 2
+        });
+        assert_eq!(result, Ok(()));
+        let nat: Vec<&Event> = events
+            .iter()
+            .filter(|e| matches!(e, Event::Nat(_) | Event::Wakeup(_)))
+            .collect();
+        let packet = |source, y| Packet {
+            source,
+            destination: 255,
+            x: 0,
+            y,
+        };
+        assert_eq!(
+            nat,
+            vec![
+                &Event::Nat(packet(19, 19)),
+                &Event::Wakeup(packet(19, 19)),
+                &Event::Nat(packet(19, 39)),
+            ]
+        );
+        assert_eq!(events.len(), 2 * 20 + 1);
+    }
+
+    #[test]
+    fn undeliverable_and_halt() {
+        // Every node sends one packet to address 1000 and halts.
+        let program = assemble("in [0]\nout #1000\nout #1\nout #2\nhlt").unwrap();
+        let mut undeliverable = 0;
+        let result = Network::new(program, 10).run(|event| {
+            assert!(matches!(event, Event::Undeliverable(_)));
+            undeliverable += 1;
+            true
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(undeliverable, 10);
+    }
+
+    #[test]
+    fn packets_to_stopped_nodes() {
+        // Node 2 halts, node 1 sends a packet to the NAT and node 0 forwards whatever it receives
+        // to node 2.
+        let program = assemble(
+            "
+                in [address]
+                eq [address], #2, [tmp]
+                jnz [tmp], #halt
+                jnz [address], #nat
+            forward:
+                in [x]
+                eq [x], #-1, [tmp]
+                jnz [tmp], #forward
+                in [y]
+                out #2
+                out [x]
+                out [y]
+                jz #0, #forward
+            nat:
+                out #255
+                out #1
+                out #2
+            idle:
+                in [x]
+                jz #0, #idle
+            halt:
+                hlt
+            address: db 0
+            x: db 0
+            y: db 0
+            tmp: db 0
+            ",
+        )
+        .unwrap();
+        let mut events = vec![];
+        let result = Network::new(program, 3).run(|event| {
+            events.push(*event);
+            events.len() < 5
+        });
+        assert_eq!(result, Ok(()));
+        let nat = Packet {
+            source: 1,
+            destination: 255,
+            x: 1,
+            y: 2,
+        };
+        let forwarded = Packet {
+            source: 0,
+            destination: 2,
+            x: 1,
+            y: 2,
+        };
+        // Dropped packets aren't in flight, so the network becomes idle again.
+        assert_eq!(
+            events,
+            vec![
+                Event::Nat(nat),
+                Event::Wakeup(nat),
+                Event::Undeliverable(forwarded),
+                Event::Wakeup(nat),
+                Event::Undeliverable(forwarded),
+            ]
+        );
+    }
+
+    #[test]
+    fn stopped_nat_destination() {
+        // Node 0 halts and node 1 sends a packet to the NAT.
+        let program = assemble(
+            "
+                in [address]
+                jz [address], #halt
+                out #255
+                out #1
+                out #2
+            idle:
+                in [x]
+                jz #0, #idle
+            halt:
+                hlt
+            address: db 0
+            x: db 0
+            ",
+        )
+        .unwrap();
+        let mut events = vec![];
+        let result = Network::new(program, 2).run(|event| {
+            events.push(*event);
+            true
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn node_without_input() {
+        // Every node sends a packet and then loops without asking for input.
+        let program = assemble("in [0]\nout #1000\nout #1\nout #2\nloop: jz #0, #loop").unwrap();
+        // run joins every node, so it only returns once the looping nodes have been stopped.
+        let result = Network::new(program, 2).run(|_| false);
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn node_failure() {
+        let result = Network::new(vec![3, 0, 42], 3).run(|_| true);
+        assert_eq!(
+            result.unwrap_err().error,
+            VmError::IllegalOpcode {
+                pc: 2,
+                instruction: 42
+            }
+        );
+    }
+}
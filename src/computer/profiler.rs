@@ -0,0 +1,187 @@
+use super::disassembler::disassemble_line;
+use super::memory::Memory;
+use super::word::Word;
+use super::Computer;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+// Execution counts and timings collected by a Computer created with_profiler().
+//
+// Time is only measured while instructions execute, so a Computer paused waiting for input or
+// between resumes doesn't add to it. The time between I/O events is the execution time from one
+// input or output instruction to the next, or from the first step to the first I/O event.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    steps: u64,
+    time: Duration,
+    opcodes: HashMap<&'static str, u64>,
+    addresses: HashMap<usize, u64>,
+    io_gaps: Vec<Duration>,
+    since_io: Duration,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn record(&mut self, pc: usize, opcode: &'static str, io: bool, time: Duration) {
+        self.steps += 1;
+        self.time += time;
+        *self.opcodes.entry(opcode).or_insert(0) += 1;
+        *self.addresses.entry(pc).or_insert(0) += 1;
+        self.since_io += time;
+        if io {
+            self.io_gaps.push(self.since_io);
+            self.since_io = Duration::default();
+        }
+    }
+
+    // Adds the counts of another profile, e.g. to sum up many short lived Computers running the
+    // same program.
+    pub fn merge(&mut self, other: &Profile) {
+        self.steps += other.steps;
+        self.time += other.time;
+        for (opcode, count) in &other.opcodes {
+            *self.opcodes.entry(opcode).or_insert(0) += count;
+        }
+        for (address, count) in &other.addresses {
+            *self.addresses.entry(*address).or_insert(0) += count;
+        }
+        self.io_gaps.extend(&other.io_gaps);
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+    // Executions per OpCode variant, most executed first.
+    pub fn opcodes(&self) -> Vec<(&'static str, u64)> {
+        let mut opcodes: Vec<(&'static str, u64)> =
+            self.opcodes.iter().map(|(op, n)| (*op, *n)).collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        opcodes
+    }
+    // Executions per instruction address, most executed first.
+    pub fn addresses(&self) -> Vec<(usize, u64)> {
+        let mut addresses: Vec<(usize, u64)> =
+            self.addresses.iter().map(|(a, n)| (*a, *n)).collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        addresses
+    }
+    pub fn io_gaps(&self) -> &[Duration] {
+        &self.io_gaps
+    }
+
+    // Hot spot report listing the opcode counts and the top most executed addresses next to
+    // their disassembly in program.
+    pub fn report(&self, program: &[i64], top: usize) -> String {
+        let mut out = String::new();
+        let percent = |count: u64| 100.0 * count as f64 / self.steps.max(1) as f64;
+        writeln!(out, "steps {} in {:?}", self.steps, self.time).unwrap();
+        writeln!(out, "opcodes:").unwrap();
+        for (opcode, count) in self.opcodes() {
+            writeln!(
+                out,
+                "  {:<20}{:>12} {:>6.2}%",
+                opcode,
+                count,
+                percent(count)
+            )
+            .unwrap();
+        }
+        writeln!(out, "hot spots:").unwrap();
+        for (address, count) in self.addresses().into_iter().take(top) {
+            let line = if address < program.len() {
+                disassemble_line(program, address).to_string()
+            } else {
+                format!("{:>6}: db 0", address)
+            };
+            writeln!(out, "  {:>12} {:>6.2}%  {}", count, percent(count), line).unwrap();
+        }
+        let longest = self.io_gaps.iter().max().copied().unwrap_or_default();
+        let total: Duration = self.io_gaps.iter().sum();
+        writeln!(
+            out,
+            "io events {}, time between them {:?} in total, {:?} at most",
+            self.io_gaps.len(),
+            total,
+            longest
+        )
+        .unwrap();
+        out
+    }
+}
+
+impl<W: Word, M: Memory<Word = W>> Computer<W, M> {
+    // Counts executed instructions per opcode and address, see Profile.
+    pub fn with_profiler(mut self) -> Self {
+        self.profile = Some(Profile::new());
+        self
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::DOUBLER;
+    use super::*;
+
+    fn profile(inputs: Vec<i64>) -> Profile {
+        let mut c = Computer::new(DOUBLER.to_vec()).with_profiler();
+        c.run_to_halt(inputs).unwrap();
+        c.profile().unwrap().clone()
+    }
+
+    #[test]
+    fn counts() {
+        let p = profile(vec![1, 2, 0]);
+        assert_eq!(p.steps(), 13);
+        assert_eq!(
+            p.opcodes(),
+            vec![
+                ("Input", 3),
+                ("JumpIfFalse", 3),
+                ("JumpIfTrue", 2),
+                ("Multiply", 2),
+                ("Output", 2),
+                ("Halt", 1),
+            ]
+        );
+        assert_eq!(
+            p.addresses(),
+            vec![(0, 3), (2, 3), (5, 2), (9, 2), (11, 2), (14, 1)]
+        );
+        assert_eq!(p.io_gaps().len(), 5);
+        assert!(Computer::new(DOUBLER.to_vec()).profile().is_none());
+    }
+
+    #[test]
+    fn merge() {
+        let mut p = profile(vec![1, 0]);
+        p.merge(&profile(vec![0]));
+        assert_eq!(p.steps(), 8 + 3);
+        assert_eq!(p.addresses()[..2], [(0, 3), (2, 3)]);
+        assert_eq!(p.io_gaps().len(), 3 + 1);
+    }
+
+    #[test]
+    fn report() {
+        let report = profile(vec![1, 2, 0]).report(&DOUBLER, 2);
+        let lines: Vec<&str> = report.lines().collect();
+        assert!(lines[0].starts_with("steps 13 in "));
+        assert_eq!(lines[1], "opcodes:");
+        assert_eq!(lines[2], "  Input                          3  23.08%");
+        assert_eq!(lines[8], "hot spots:");
+        assert_eq!(lines[9], "             3  23.08%       0: in [15]");
+        assert_eq!(lines[10], "             3  23.08%       2: jz [15], #14");
+        assert!(lines[11].starts_with("io events 5, "));
+        assert_eq!(lines.len(), 12);
+    }
+}
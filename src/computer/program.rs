@@ -0,0 +1,158 @@
+use super::word::Word;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::str::FromStr;
+
+// A token that isn't a number, with the index of the word it would have been.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub index: usize,
+    pub token: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Bad value '{}' at index {} on line {}",
+            self.token, self.index, self.line
+        )
+    }
+}
+
+impl Error for ParseError {}
+
+// Intcode program text parsed into words. Words are separated by commas and may be spread over
+// any number of lines. Whitespace around words, a comma ending a line and anything after a `#`
+// are ignored, e.g.
+//
+//   # Outputs its input.
+//   3,0,   # in [0]
+//   4,0,
+//   99
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program<W: Word = i64> {
+    words: Vec<W>,
+}
+
+impl<W: Word> Program<W> {
+    fn parse_line(&mut self, number: usize, line: &str) -> Result<(), ParseError> {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let line = line.strip_suffix(',').unwrap_or(line);
+        if line.is_empty() {
+            return Ok(());
+        }
+        for token in line.split(',') {
+            let word = token.trim().parse().map_err(|_| ParseError {
+                line: number,
+                index: self.words.len(),
+                token: token.trim().to_string(),
+            })?;
+            self.words.push(word);
+        }
+        Ok(())
+    }
+
+    // Parses lines as they come from BufRead::lines. Parse errors are reported as
+    // io::ErrorKind::InvalidData wrapping the ParseError.
+    pub fn from_lines<I: IntoIterator<Item = io::Result<String>>>(lines: I) -> io::Result<Self> {
+        let mut program = Self { words: vec![] };
+        for (i, line) in lines.into_iter().enumerate() {
+            program
+                .parse_line(i + 1, &line?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        }
+        Ok(program)
+    }
+
+    pub fn read_from(input: impl BufRead) -> io::Result<Self> {
+        Self::from_lines(input.lines())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn words(&self) -> &[W] {
+        &self.words
+    }
+
+    pub fn into_words(self) -> Vec<W> {
+        self.words
+    }
+}
+
+impl<W: Word> FromStr for Program<W> {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        let mut program = Self { words: vec![] };
+        for (i, line) in s.lines().enumerate() {
+            program.parse_line(i + 1, line)?;
+        }
+        Ok(program)
+    }
+}
+
+impl<W: Word> From<Program<W>> for Vec<W> {
+    fn from(program: Program<W>) -> Self {
+        program.words
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let program: Program = "1,9,10,3,2,3,11,0,99,30,40,50\n".parse().unwrap();
+        assert_eq!(program.words(), &[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        let program: Program = "
+            # Outputs its input.
+            3, 0,   # in [0]
+            4 ,0,
+            99
+            "
+        .parse()
+        .unwrap();
+        assert_eq!(program.into_words(), vec![3, 0, 4, 0, 99]);
+        let program: Program<i128> = "104,1267650600228229401496703205376,99".parse().unwrap();
+        assert_eq!(program.words()[1], 1 << 100);
+        assert_eq!("".parse::<Program>().unwrap().words(), &[]);
+    }
+
+    #[test]
+    fn bad_tokens() {
+        let error = |text: &str| text.parse::<Program>().unwrap_err();
+        assert_eq!(
+            error("1,2\n3,x4,5"),
+            ParseError {
+                line: 2,
+                index: 3,
+                token: "x4".to_string()
+            }
+        );
+        assert_eq!(error("1,,2").index, 1);
+        assert_eq!(error("1,,2").token, "");
+        assert_eq!(error("1 2,3").token, "1 2");
+        assert_eq!(
+            error("99,1.5").to_string(),
+            "Bad value '1.5' at index 1 on line 1"
+        );
+    }
+
+    #[test]
+    fn read_from() {
+        let program = Program::<i64>::read_from("1,0,0,0,\n99\n".as_bytes()).unwrap();
+        assert_eq!(Vec::from(program), vec![1, 0, 0, 0, 99]);
+        let error = Program::<i64>::read_from("1,0\n,0".as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "Bad value '' at index 2 on line 2");
+        assert!(Program::<i64>::load("no/such/program.txt").is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use super::memory::Memory;
+use super::word::Word;
+use super::{Computer, OverflowPolicy};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+const HEADER: &str = "intcode-snapshot";
+const VERSION: u32 = 3;
+
+// Complete state of a Computer, except for any attached tracer, profiler, input source or output
+// sink, and its step limit, deadline and history.
+//
+// The file format is line based text. A header line is followed by one `key value` line per
+// field, in this order, where lists are comma separated and may be empty:
+//
+//   intcode-snapshot 3
+//   pc 4
+//   relative_base 0
+//   memory_limit 16777216
+//   overflow error
+//   steps 2
+//   halted false
+//   output 7
+//   inputs 1,2
+//   outputs 7
+//   memory 3,9,4,9,99,0,0,0,0,7
+//
+// Older versions can still be read. Version 2 files have no halted line, and restore as not
+// halted. Version 1 files also have no overflow line. They predate the overflow policy, from when
+// release builds wrapped around on overflow, so they restore with OverflowPolicy::Wrap.
+//
+// Only snapshots of Computers with dense memory can be written to and read from files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot<W: Word = i64, M: Memory<Word = W> = Vec<W>> {
+    pc: usize,
+    relative_base: W,
+    memory_limit: usize,
+    overflow: OverflowPolicy,
+    steps: u64,
+    halted: bool,
+    output: W,
+    inputs: Vec<W>,
+    outputs: Vec<W>,
+    mem: M,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn join<W: Word>(values: &[W]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+impl<W: Word, M: Memory<Word = W>> Computer<W, M> {
+    pub fn snapshot(&self) -> Snapshot<W, M> {
+        Snapshot {
+            pc: self.pc,
+            relative_base: self.relative_base,
+            memory_limit: self.memory_limit,
+            overflow: self.overflow,
+            steps: self.steps,
+            halted: self.halted,
+            output: self.output,
+            inputs: self.inputs.iter().copied().collect(),
+            outputs: self.outputs.clone(),
+            mem: self.mem.clone(),
+        }
+    }
+}
+
+impl<W: Word, M: Memory<Word = W>> Snapshot<W, M> {
+    // Creates a new, independent Computer in the snapshotted state. Can be called any number of
+    // times to branch execution from the same point.
+    pub fn restore(&self) -> Computer<W, M> {
+        let mut c = Computer::from_memory(self.mem.clone())
+            .with_memory_limit(self.memory_limit)
+            .with_overflow(self.overflow);
+        c.pc = self.pc;
+        c.relative_base = self.relative_base;
+        c.steps = self.steps;
+        c.halted = self.halted;
+        c.output = self.output;
+        c.inputs = self.inputs.iter().copied().collect::<VecDeque<W>>();
+        c.outputs = self.outputs.clone();
+        c
+    }
+}
+
+impl<W: Word> Snapshot<W> {
+    pub fn write_to(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "{} {}", HEADER, VERSION)?;
+        writeln!(out, "pc {}", self.pc)?;
+        writeln!(out, "relative_base {}", self.relative_base)?;
+        writeln!(out, "memory_limit {}", self.memory_limit)?;
+        writeln!(out, "overflow {}", self.overflow)?;
+        writeln!(out, "steps {}", self.steps)?;
+        writeln!(out, "halted {}", self.halted)?;
+        writeln!(out, "output {}", self.output)?;
+        writeln!(out, "inputs {}", join(&self.inputs))?;
+        writeln!(out, "outputs {}", join(&self.outputs))?;
+        writeln!(out, "memory {}", join(&self.mem))?;
+        out.flush()
+    }
+
+    pub fn read_from(input: impl BufRead) -> io::Result<Self> {
+        let mut lines = input.lines();
+        let mut next = |key: &str| -> io::Result<String> {
+            let line = lines
+                .next()
+                .ok_or_else(|| invalid(format!("Missing '{}'", key)))??;
+            match line.strip_prefix(key) {
+                Some(rest) if rest.is_empty() || rest.starts_with(' ') => {
+                    Ok(rest.trim().to_string())
+                }
+                _ => Err(invalid(format!("Expected '{}', found '{}'", key, line))),
+            }
+        };
+        fn value<T: FromStr>(key: &str, text: &str) -> io::Result<T> {
+            text.parse::<T>()
+                .map_err(|_| invalid(format!("Bad {} '{}'", key, text)))
+        }
+        fn list<W: Word>(key: &str, text: &str) -> io::Result<Vec<W>> {
+            if text.is_empty() {
+                return Ok(vec![]);
+            }
+            text.split(',').map(|v| value(key, v)).collect()
+        }
+        let version: u32 = value("version", &next(HEADER)?)?;
+        if !(1..=VERSION).contains(&version) {
+            return Err(invalid(format!("Unsupported version '{}'", version)));
+        }
+        Ok(Snapshot {
+            pc: value("pc", &next("pc")?)?,
+            relative_base: value("relative_base", &next("relative_base")?)?,
+            memory_limit: value("memory_limit", &next("memory_limit")?)?,
+            overflow: match version {
+                1 => OverflowPolicy::Wrap,
+                _ => value("overflow", &next("overflow")?)?,
+            },
+            steps: value("steps", &next("steps")?)?,
+            halted: match version {
+                1 | 2 => false,
+                _ => value("halted", &next("halted")?)?,
+            },
+            output: value("output", &next("output")?)?,
+            inputs: list("inputs", &next("inputs")?)?,
+            outputs: list("outputs", &next("outputs")?)?,
+            mem: list("memory", &next("memory")?)?,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{State, DOUBLER};
+    use super::*;
+
+    fn doubler() -> Computer {
+        Computer::new(DOUBLER.to_vec())
+    }
+
+    #[test]
+    fn file_format() {
+        let mut c = doubler();
+        c.push_input(3);
+        c.push_input(4);
+        assert_eq!(c.resume(), Ok(State::Output(6)));
+        let mut text = vec![];
+        c.snapshot().write_to(&mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "intcode-snapshot 3\n\
+             pc 11\n\
+             relative_base 0\n\
+             memory_limit 16777216\n\
+             overflow error\n\
+             steps 4\n\
+             halted false\n\
+             output 6\n\
+             inputs 4\n\
+             outputs 6\n\
+             memory 3,15,1006,15,14,1002,15,2,15,4,15,1105,1,0,99,6\n"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut c = doubler();
+        c.push_input(5);
+        c.resume().unwrap();
+        let snapshot = c.snapshot();
+        let mut text = vec![];
+        snapshot.write_to(&mut text).unwrap();
+        assert_eq!(Snapshot::read_from(&text[..]).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn branch_from_snapshot() {
+        let mut c = doubler();
+        c.push_input(1);
+        assert_eq!(c.resume(), Ok(State::Output(2)));
+        let snapshot = c.snapshot();
+        let mut a = snapshot.restore();
+        let mut b = snapshot.restore();
+        a.push_input(10);
+        b.push_input(20);
+        assert_eq!(a.resume(), Ok(State::Output(20)));
+        assert_eq!(b.resume(), Ok(State::Output(40)));
+        assert_eq!(a.drain_outputs(), vec![2, 20]);
+        assert_eq!(c.resume(), Ok(State::NeedsInput));
+    }
+
+    #[test]
+    fn bad_snapshot() {
+        let text = "intcode-snapshot 3\npc 0\nrelative_base x\n";
+        let e = Snapshot::<i64>::read_from(text.as_bytes()).unwrap_err();
+        assert_eq!(e.to_string(), "Bad relative_base 'x'");
+        let e = Snapshot::<i64>::read_from("intcode-snapshot 3\npc 0\n".as_bytes()).unwrap_err();
+        assert_eq!(e.to_string(), "Missing 'relative_base'");
+        let e = Snapshot::<i64>::read_from("intcode-snapshot 4\n".as_bytes()).unwrap_err();
+        assert_eq!(e.to_string(), "Unsupported version '4'");
+        let e = Snapshot::<i64>::read_from("intcode-snapshot x\n".as_bytes()).unwrap_err();
+        assert_eq!(e.to_string(), "Bad version 'x'");
+    }
+
+    #[test]
+    fn halted() {
+        let mut c = Computer::new(vec![1101, 1, 2, 5, 99, 0]);
+        assert_eq!(c.run_to_halt(vec![]), Ok(vec![]));
+        let mut text = vec![];
+        c.snapshot().write_to(&mut text).unwrap();
+        let mut restored = Snapshot::read_from(&text[..]).unwrap().restore();
+        assert_eq!(restored.step(), Ok(Some(State::Halted)));
+        assert_eq!(restored.steps(), 2);
+        assert_eq!(restored.snapshot(), c.snapshot());
+    }
+
+    #[test]
+    fn version_2() {
+        let text = "intcode-snapshot 2\n\
+                    pc 4\n\
+                    relative_base 0\n\
+                    memory_limit 16777216\n\
+                    overflow saturate\n\
+                    steps 1\n\
+                    output 0\n\
+                    inputs \n\
+                    outputs \n\
+                    memory 1101,1,2,5,99,3\n";
+        let mut c = Snapshot::read_from(text.as_bytes()).unwrap().restore();
+        let mut expected =
+            Computer::new(vec![1101, 1, 2, 5, 99, 0]).with_overflow(OverflowPolicy::Saturate);
+        expected.step().unwrap();
+        assert_eq!(c.snapshot(), expected.snapshot());
+        assert_eq!(c.step(), Ok(Some(State::Halted)));
+        assert_eq!(c.steps(), 2);
+    }
+
+    #[test]
+    fn version_1() {
+        let text = "intcode-snapshot 1\n\
+                    pc 11\n\
+                    relative_base 0\n\
+                    memory_limit 16777216\n\
+                    steps 4\n\
+                    output 6\n\
+                    inputs 4\n\
+                    outputs 6\n\
+                    memory 3,15,1006,15,14,1002,15,2,15,4,15,1105,1,0,99,6\n";
+        let mut c = Snapshot::read_from(text.as_bytes()).unwrap().restore();
+        let mut expected = doubler().with_overflow(OverflowPolicy::Wrap);
+        expected.push_input(3);
+        expected.push_input(4);
+        expected.resume().unwrap();
+        assert_eq!(c.snapshot(), expected.snapshot());
+        assert_eq!(c.resume(), Ok(State::Output(8)));
+    }
+}
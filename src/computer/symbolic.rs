@@ -0,0 +1,599 @@
+use super::{decode, Computer, PARAM_MODE_IMMEDIATE, PARAM_MODE_POSITION, PARAM_MODE_RELATIVE};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+// Bounds symbolic execution and each run of the search, for programs that don't halt.
+const STEP_LIMIT: u64 = 1_000_000;
+// Largest memory a symbolically executed program may grow to.
+const MEMORY_LIMIT: usize = 1 << 20;
+
+// Affine combination of symbols, constant + coefficients[0] * x0 + coefficients[1] * x1 + ...
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Affine {
+    pub constant: i64,
+    pub coefficients: Vec<i64>,
+}
+
+impl Affine {
+    pub fn constant(value: i64) -> Self {
+        Self {
+            constant: value,
+            coefficients: vec![],
+        }
+    }
+
+    pub fn symbol(index: usize) -> Self {
+        let mut coefficients = vec![0; index + 1];
+        coefficients[index] = 1;
+        Self {
+            constant: 0,
+            coefficients,
+        }
+    }
+
+    fn coefficient(&self, index: usize) -> i64 {
+        self.coefficients.get(index).copied().unwrap_or(0)
+    }
+
+    // Value, if it doesn't depend on any symbol.
+    pub fn as_constant(&self) -> Option<i64> {
+        if self.coefficients.iter().all(|c| *c == 0) {
+            Some(self.constant)
+        } else {
+            None
+        }
+    }
+
+    // Value with the symbols replaced by values.
+    pub fn eval(&self, values: &[i64]) -> Option<i64> {
+        let mut sum = self.constant;
+        for (i, value) in values.iter().enumerate() {
+            sum = sum.checked_add(self.coefficient(i).checked_mul(*value)?)?;
+        }
+        Some(sum)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        let len = self.coefficients.len().max(other.coefficients.len());
+        Some(Self {
+            constant: self.constant.checked_add(other.constant)?,
+            coefficients: (0..len)
+                .map(|i| self.coefficient(i).checked_add(other.coefficient(i)))
+                .collect::<Option<Vec<i64>>>()?,
+        })
+    }
+
+    fn checked_scale(&self, factor: i64) -> Option<Self> {
+        Some(Self {
+            constant: self.constant.checked_mul(factor)?,
+            coefficients: self
+                .coefficients
+                .iter()
+                .map(|c| c.checked_mul(factor))
+                .collect::<Option<Vec<i64>>>()?,
+        })
+    }
+}
+
+impl fmt::Display for Affine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.constant)?;
+        for (i, c) in self.coefficients.iter().enumerate() {
+            match c {
+                0 => (),
+                1 => write!(f, " + x{}", i)?,
+                c => write!(f, " + {}*x{}", c, i)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Why a value couldn't be tracked, or a program couldn't be executed, symbolically.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolicError {
+    // Multiplication of two values that both depend on symbols.
+    NotAffine { pc: usize, instruction: i64 },
+    // A value that depends on symbols used as an address or relative base adjustment.
+    SymbolicAddress { pc: usize, instruction: i64 },
+    // A jump or comparison on a value that depends on symbols.
+    SymbolicBranch { pc: usize, instruction: i64 },
+    // The instruction word itself depends on symbols.
+    SymbolicCode { pc: usize },
+    // I/O, illegal opcodes and modes, overflow and addresses out of range.
+    Unsupported { pc: usize, instruction: i64 },
+    StepLimit,
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::NotAffine { pc, instruction } => write!(
+                f,
+                "Product of symbolic values in instruction {} at pc {}",
+                instruction, pc
+            ),
+            SymbolicError::SymbolicAddress { pc, instruction } => write!(
+                f,
+                "Symbolic address in instruction {} at pc {}",
+                instruction, pc
+            ),
+            SymbolicError::SymbolicBranch { pc, instruction } => write!(
+                f,
+                "Jump or comparison on symbolic value in instruction {} at pc {}",
+                instruction, pc
+            ),
+            SymbolicError::SymbolicCode { pc } => write!(f, "Symbolic instruction at pc {}", pc),
+            SymbolicError::Unsupported { pc, instruction } => write!(
+                f,
+                "Can't execute instruction {} at pc {} symbolically",
+                instruction, pc
+            ),
+            SymbolicError::StepLimit => write!(f, "Step limit reached"),
+        }
+    }
+}
+
+impl Error for SymbolicError {}
+
+// Content of a memory cell: an affine expression, or why it isn't known as one. Unknown values
+// are fine as long as they don't affect control flow, addresses or the result, e.g. the sum that
+// day 2 programs compute from the noun and verb as addresses and immediately overwrite.
+type Value = Result<Affine, SymbolicError>;
+
+// Intcode interpreter over affine values, decoding instructions like Computer does. Only what
+// keeps control flow and addresses independent of the symbols is supported.
+struct Machine {
+    mem: Vec<Value>,
+    pc: usize,
+    relative_base: i64,
+    instruction: i64,
+    modes: [u8; 3],
+}
+
+impl Machine {
+    fn get(&self, address: usize) -> Value {
+        self.mem
+            .get(address)
+            .cloned()
+            .unwrap_or_else(|| Ok(Affine::constant(0)))
+    }
+
+    fn error(&self, make: fn(usize, i64) -> SymbolicError) -> SymbolicError {
+        make(self.pc, self.instruction)
+    }
+
+    fn address(&self, value: i64, base: i64) -> Result<usize, SymbolicError> {
+        match value.checked_add(base) {
+            Some(address) if (0..MEMORY_LIMIT as i64).contains(&address) => Ok(address as usize),
+            _ => Err(self.error(unsupported)),
+        }
+    }
+
+    // Address a parameter refers to, with immediate mode treated as position mode like Computer
+    // does for writes. The outer error stops execution, the inner one means the address is unknown.
+    fn param_address(&self, index: usize) -> Result<Result<usize, SymbolicError>, SymbolicError> {
+        let base = match self.modes[index] as usize {
+            PARAM_MODE_RELATIVE => self.relative_base,
+            _ => 0,
+        };
+        let raw = self.get(self.pc + index + 1);
+        Ok(match raw.map(|v| v.as_constant()) {
+            Ok(Some(value)) => Ok(self.address(value, base)?),
+            Ok(None) => Err(self.error(symbolic_address)),
+            Err(e) => Err(e),
+        })
+    }
+
+    fn param(&self, index: usize) -> Result<Value, SymbolicError> {
+        if self.modes[index] as usize == PARAM_MODE_IMMEDIATE {
+            return Ok(self.get(self.pc + index + 1));
+        }
+        Ok(self
+            .param_address(index)?
+            .and_then(|address| self.get(address)))
+    }
+
+    // A write to an unknown address could change any cell, so that fails right away.
+    fn target(&self, index: usize) -> Result<usize, SymbolicError> {
+        self.param_address(index)?
+    }
+
+    // Value of a parameter that decides control flow or an address, which must be known.
+    fn concrete(
+        &self,
+        index: usize,
+        make: fn(usize, i64) -> SymbolicError,
+    ) -> Result<i64, SymbolicError> {
+        match self.param(index)?.map(|v| v.as_constant()) {
+            Ok(Some(value)) => Ok(value),
+            _ => Err(self.error(make)),
+        }
+    }
+
+    fn set(&mut self, address: usize, value: Value) {
+        if address >= self.mem.len() {
+            self.mem.resize(address + 1, Ok(Affine::constant(0)));
+        }
+        self.mem[address] = value;
+    }
+
+    fn run(mut self) -> Result<Vec<Value>, SymbolicError> {
+        for _ in 0..STEP_LIMIT {
+            self.instruction = match self.get(self.pc).map(|v| v.as_constant()) {
+                Ok(Some(instruction)) => instruction,
+                _ => return Err(SymbolicError::SymbolicCode { pc: self.pc }),
+            };
+            let decoded = decode(self.instruction).map_err(|_| self.error(unsupported))?;
+            self.modes = decoded.modes;
+            let mut next = self.pc + decoded.size as usize;
+            match decoded.opcode {
+                1 => {
+                    let (a, b, r) = (self.param(0)?, self.param(1)?, self.target(2)?);
+                    let overflow = self.error(unsupported);
+                    self.set(r, a.and_then(|a| a.checked_add(&b?).ok_or(overflow)));
+                }
+                2 => {
+                    let (a, b, r) = (self.param(0)?, self.param(1)?, self.target(2)?);
+                    let product = match (a, b) {
+                        (Ok(a), Ok(b)) => match (a.as_constant(), b.as_constant()) {
+                            (Some(factor), _) => b.checked_scale(factor),
+                            (None, Some(factor)) => a.checked_scale(factor),
+                            (None, None) => None,
+                        }
+                        .ok_or_else(|| {
+                            match a.as_constant().or(b.as_constant()) {
+                                Some(_) => self.error(unsupported),
+                                None => self.error(not_affine),
+                            }
+                        }),
+                        (Err(e), _) | (_, Err(e)) => Err(e),
+                    };
+                    self.set(r, product);
+                }
+                opcode @ (5 | 6) => {
+                    let a = self.concrete(0, symbolic_branch)?;
+                    if (opcode == 5) == (a != 0) {
+                        let d = self.concrete(1, symbolic_address)?;
+                        next = self.address(d, 0)?;
+                    }
+                }
+                opcode @ (7 | 8) => {
+                    let (a, b, r) = (self.param(0)?, self.param(1)?, self.target(2)?);
+                    let result = match (a.map(|v| v.as_constant()), b.map(|v| v.as_constant())) {
+                        (Ok(Some(a)), Ok(Some(b))) => {
+                            let result = if opcode == 7 { a < b } else { a == b };
+                            Ok(Affine::constant(if result { 1 } else { 0 }))
+                        }
+                        _ => Err(self.error(symbolic_branch)),
+                    };
+                    self.set(r, result);
+                }
+                9 => {
+                    let a = self.concrete(0, symbolic_address)?;
+                    self.relative_base = self
+                        .relative_base
+                        .checked_add(a)
+                        .ok_or_else(|| self.error(unsupported))?;
+                }
+                99 => return Ok(self.mem),
+                // Input and output.
+                _ => return Err(self.error(unsupported)),
+            }
+            self.pc = next;
+        }
+        Err(SymbolicError::StepLimit)
+    }
+}
+
+fn not_affine(pc: usize, instruction: i64) -> SymbolicError {
+    SymbolicError::NotAffine { pc, instruction }
+}
+
+fn symbolic_address(pc: usize, instruction: i64) -> SymbolicError {
+    SymbolicError::SymbolicAddress { pc, instruction }
+}
+
+fn symbolic_branch(pc: usize, instruction: i64) -> SymbolicError {
+    SymbolicError::SymbolicBranch { pc, instruction }
+}
+
+fn unsupported(pc: usize, instruction: i64) -> SymbolicError {
+    SymbolicError::Unsupported { pc, instruction }
+}
+
+// Runs program with the cells at the given addresses holding symbols x0, x1, ..., and returns
+// what address holds at halt as an expression in them.
+pub fn run_symbolic(
+    program: &[i64],
+    symbols: &[usize],
+    address: usize,
+) -> Result<Affine, SymbolicError> {
+    let mut machine = Machine {
+        mem: program.iter().map(|v| Ok(Affine::constant(*v))).collect(),
+        pc: 0,
+        relative_base: 0,
+        instruction: 0,
+        modes: [PARAM_MODE_POSITION as u8; 3],
+    };
+    for (i, symbol) in symbols.iter().enumerate() {
+        machine.set(*symbol, Ok(Affine::symbol(i)));
+    }
+    let mem = machine.run()?;
+    mem.get(address)
+        .cloned()
+        .unwrap_or_else(|| Ok(Affine::constant(0)))
+}
+
+// First assignment of values from ranges, in lexicographic order, for which expression equals
+// target. Every symbol but the last is enumerated, and the last is solved for.
+pub fn solve_affine(expression: &Affine, target: i64, ranges: &[Range<i64>]) -> Option<Vec<i64>> {
+    let (last, rest) = ranges.split_last()?;
+    let c = expression.coefficient(rest.len()) as i128;
+    // What the last term must add up to, given values for the other symbols. Wide enough that the
+    // arithmetic can't overflow.
+    let remainder = |values: &[i64]| {
+        values
+            .iter()
+            .enumerate()
+            .fold(target as i128 - expression.constant as i128, |r, (i, v)| {
+                r - expression.coefficient(i) as i128 * *v as i128
+            })
+    };
+    let last_value = |values: &[i64]| {
+        let value = match (c, remainder(values)) {
+            (0, 0) => last.start,
+            (0, _) => return None,
+            (c, r) if r % c == 0 => i64::try_from(r / c).ok()?,
+            _ => return None,
+        };
+        Some(value).filter(|v| last.contains(v))
+    };
+    let mut values = vec![];
+    if !find(
+        rest,
+        &mut values,
+        &|values| last_value(values).is_some(),
+        &|| false,
+    ) {
+        return None;
+    }
+    values.push(last_value(&values)?);
+    Some(values)
+}
+
+// Depth first search for the first values from ranges, extending values, that pass test.
+fn find(
+    ranges: &[Range<i64>],
+    values: &mut Vec<i64>,
+    test: &dyn Fn(&[i64]) -> bool,
+    stop: &dyn Fn() -> bool,
+) -> bool {
+    let range = match ranges.get(values.len()) {
+        Some(range) => range.clone(),
+        None => return test(values),
+    };
+    for value in range {
+        if stop() {
+            return false;
+        }
+        values.push(value);
+        if find(ranges, values, test, stop) {
+            return true;
+        }
+        values.pop();
+    }
+    false
+}
+
+// Whether running program with the values stored at the addresses of unknowns leaves target at
+// address.
+fn satisfies(
+    program: &[i64],
+    unknowns: &[(usize, Range<i64>)],
+    values: &[i64],
+    address: usize,
+    target: i64,
+) -> bool {
+    let mut c = Computer::new(program.to_vec()).with_step_limit(STEP_LIMIT);
+    for ((a, _), value) in unknowns.iter().zip(values) {
+        if c.poke(*a, *value).is_err() {
+            return false;
+        }
+    }
+    c.run_to_halt(vec![]).is_ok() && c.peek(address) == target
+}
+
+// Finds values for the unknowns, each an address and the range of values to try there, for which
+// program halts with target at address. Symbolic execution is used if the result is affine in the
+// unknowns. Returns the first solution in lexicographic order.
+pub fn solve(
+    program: &[i64],
+    unknowns: &[(usize, Range<i64>)],
+    address: usize,
+    target: i64,
+) -> Option<Vec<i64>> {
+    let symbols: Vec<usize> = unknowns.iter().map(|(a, _)| *a).collect();
+    let ranges: Vec<Range<i64>> = unknowns.iter().map(|(_, r)| r.clone()).collect();
+    let solution = run_symbolic(program, &symbols, address)
+        .map(|expression| solve_affine(&expression, target, &ranges));
+    match solution {
+        Ok(Some(values)) if satisfies(program, unknowns, &values, address, target) => Some(values),
+        Ok(None) => None,
+        // Not affine, or the Computer disagrees, e.g. because it stops on overflow.
+        _ => search(program, unknowns, address, target),
+    }
+}
+
+// Brute force version of solve, running the program for every combination of values. The range
+// of the first unknown is split between threads.
+pub fn search(
+    program: &[i64],
+    unknowns: &[(usize, Range<i64>)],
+    address: usize,
+    target: i64,
+) -> Option<Vec<i64>> {
+    let ranges: Vec<Range<i64>> = unknowns.iter().map(|(_, r)| r.clone()).collect();
+    let test = |values: &[i64]| satisfies(program, unknowns, values, address, target);
+    let first = match ranges.first() {
+        Some(first) => first.clone(),
+        None => return if test(&[]) { Some(vec![]) } else { None },
+    };
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    // The width of a range can exceed i64, so chunks are computed in i128. Every chunk bound is
+    // clamped to the range and so fits in an i64 again.
+    let (low, high) = (i128::from(first.start), i128::from(first.end));
+    let chunk = (((high - low).max(0) + threads as i128 - 1) / threads as i128).max(1);
+    // Lowest chunk that found a solution. Chunks above it can stop, since their solutions come
+    // later in lexicographic order.
+    let found = AtomicUsize::new(usize::MAX);
+    let results: Vec<Option<Vec<i64>>> = thread::scope(|scope| {
+        let (ranges, test, found) = (&ranges, &test, &found);
+        let handles: Vec<_> = (0..threads)
+            .map(|i| {
+                scope.spawn(move || {
+                    let start = (low + i as i128 * chunk).min(high);
+                    let end = (start + chunk).min(high);
+                    let mut chunk_ranges = ranges.clone();
+                    chunk_ranges[0] = start as i64..end as i64;
+                    let mut values = vec![];
+                    let stop = || found.load(Ordering::Relaxed) < i;
+                    if find(&chunk_ranges, &mut values, test, &stop) {
+                        found.fetch_min(i, Ordering::Relaxed);
+                        Some(values)
+                    } else {
+                        None
+                    }
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    results.into_iter().flatten().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::program::Program;
+    use super::*;
+
+    // Leaves 3 * (x0 + x1) at address 0, with x0 at 9 and x1 at 10.
+    const SUM_TIMES_THREE: [i64; 12] = [1, 9, 10, 0, 2, 0, 11, 0, 99, 0, 0, 3];
+    // Leaves x0 * x1 at address 0, with x0 at 5 and x1 at 6.
+    const PRODUCT: [i64; 7] = [2, 5, 6, 0, 99, 0, 0];
+
+    #[test]
+    fn affine() {
+        let expression = run_symbolic(&SUM_TIMES_THREE, &[9, 10], 0).unwrap();
+        assert_eq!(
+            expression,
+            Affine {
+                constant: 0,
+                coefficients: vec![3, 3]
+            }
+        );
+        assert_eq!(expression.to_string(), "0 + 3*x0 + 3*x1");
+        assert_eq!(expression.eval(&[2, 5]), Some(21));
+        assert_eq!(
+            solve_affine(&expression, 30, &[0..10, 0..10]),
+            Some(vec![1, 9])
+        );
+        assert_eq!(solve_affine(&expression, 31, &[0..10, 0..10]), None);
+        let unknowns = [(9, 0..10), (10, 0..10)];
+        assert_eq!(solve(&SUM_TIMES_THREE, &unknowns, 0, 30), Some(vec![1, 9]));
+        assert_eq!(search(&SUM_TIMES_THREE, &unknowns, 0, 30), Some(vec![1, 9]));
+    }
+
+    #[test]
+    fn unknown_values() {
+        // Like day 2 programs, first adds the values at addresses x0 and x1, then overwrites the
+        // sum with x0 + x1 and leaves 100 * (x0 + x1) at address 0.
+        let program = [1, 0, 0, 3, 1, 1, 2, 3, 2, 3, 13, 0, 99, 100];
+        assert_eq!(
+            run_symbolic(&program, &[1, 2], 0),
+            Ok(Affine {
+                constant: 0,
+                coefficients: vec![100, 100]
+            })
+        );
+        assert_eq!(
+            run_symbolic(&program, &[1, 2], 3),
+            Ok(Affine {
+                constant: 0,
+                coefficients: vec![1, 1]
+            })
+        );
+        assert_eq!(
+            solve(&program, &[(1, 0..10), (2, 0..10)], 0, 1500),
+            Some(vec![6, 9])
+        );
+        // Without the overwrite, the sum stays unknown.
+        let program = [1, 0, 0, 0, 99];
+        assert_eq!(
+            run_symbolic(&program, &[1, 2], 0),
+            Err(SymbolicError::SymbolicAddress {
+                pc: 0,
+                instruction: 1
+            })
+        );
+    }
+
+    #[test]
+    fn fallback() {
+        assert_eq!(
+            run_symbolic(&PRODUCT, &[5, 6], 0),
+            Err(SymbolicError::NotAffine {
+                pc: 0,
+                instruction: 2
+            })
+        );
+        let unknowns = [(5, 0..10), (6, 0..10)];
+        assert_eq!(solve(&PRODUCT, &unknowns, 0, 12), Some(vec![2, 6]));
+        assert_eq!(solve(&PRODUCT, &unknowns, 0, 11), None);
+        // Halts if x0 at 11 is zero, else leaves 2 * x0 at address 0.
+        let program = [1005, 11, 6, 99, 0, 0, 1002, 11, 2, 0, 99, 0];
+        assert!(matches!(
+            run_symbolic(&program, &[11], 0),
+            Err(SymbolicError::SymbolicBranch { pc: 0, .. })
+        ));
+        assert_eq!(solve(&program, &[(11, 0..100)], 0, 14), Some(vec![7]));
+    }
+
+    #[test]
+    fn wide_search() {
+        // The range is wider than an i64, and its first value is the solution.
+        let unknowns = [(5, -5..i64::MAX), (6, 3..4)];
+        assert_eq!(search(&PRODUCT, &unknowns, 0, -15), Some(vec![-5, 3]));
+        assert_eq!(search(&PRODUCT, &[(5, 5..5), (6, 3..4)], 0, 0), None);
+    }
+
+    #[test]
+    fn day2() {
+        let program = Program::load("input/day2.txt").unwrap().into_words();
+        assert_eq!(
+            run_symbolic(&program, &[1, 2], 0).map(|e| e.to_string()),
+            Ok("106699 + 384000*x0 + x1".to_string())
+        );
+        let unknowns = [(1, 0..100), (2, 0..100)];
+        assert_eq!(solve(&program, &unknowns, 0, 19690720), Some(vec![51, 21]));
+    }
+
+    #[test]
+    fn solve_affine_edge_cases() {
+        // x1 doesn't matter, so the lowest value is picked for it.
+        let expression = Affine {
+            constant: 1,
+            coefficients: vec![2],
+        };
+        assert_eq!(
+            solve_affine(&expression, 7, &[0..5, 3..5]),
+            Some(vec![3, 3])
+        );
+        assert_eq!(solve_affine(&expression, 7, &[0..5, 3..3]), None);
+        assert_eq!(solve_affine(&expression, 7, &[]), None);
+    }
+}
@@ -0,0 +1,181 @@
+use super::{Computer, State, VmError};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopologyError {
+    UnknownNode(String),
+    // No node could make progress before the awaited node halted.
+    Deadlock,
+    Failed { node: String, error: VmError },
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TopologyError::UnknownNode(name) => write!(f, "Unknown node '{}'", name),
+            TopologyError::Deadlock => write!(f, "All nodes are waiting for input"),
+            TopologyError::Failed { node, error } => write!(f, "Node '{}' failed: {}", node, error),
+        }
+    }
+}
+
+impl Error for TopologyError {}
+
+struct Node {
+    name: String,
+    computer: Computer,
+    halted: bool,
+}
+
+// Describes a set of named Computers whose outputs feed the inputs of other Computers, e.g. the
+// day 7 amplifier chain:
+//
+//   Topology::new()
+//       .node("A", program.clone(), vec![phase_a])
+//       .node("B", program.clone(), vec![phase_b])
+//       .edge("A", "B")
+//       .edge("B", "A")
+//       .input("A", 0)
+//       .run_until_halted("B")
+//
+// Every output of a node is sent to all nodes it has an edge to, in the order the edges were
+// added. Nodes run one at a time in the order they were added, each until it halts or needs input.
+#[derive(Default)]
+pub struct Topology {
+    nodes: Vec<Node>,
+    edges: Vec<(String, String)>,
+    inputs: Vec<(String, i64)>,
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a node running program, with initial inputs such as a phase setting.
+    pub fn node(mut self, name: &str, program: Vec<i64>, inputs: Vec<i64>) -> Self {
+        let mut computer = Computer::new(program);
+        for input in inputs {
+            computer.push_input(input);
+        }
+        self.nodes.push(Node {
+            name: name.to_string(),
+            computer,
+            halted: false,
+        });
+        self
+    }
+
+    pub fn edge(mut self, from: &str, to: &str) -> Self {
+        self.edges.push((from.to_string(), to.to_string()));
+        self
+    }
+
+    // Queues an input value for a node after its initial inputs.
+    pub fn input(mut self, name: &str, value: i64) -> Self {
+        self.inputs.push((name.to_string(), value));
+        self
+    }
+
+    fn index(&self, name: &str) -> Result<usize, TopologyError> {
+        self.nodes
+            .iter()
+            .position(|n| n.name == name)
+            .ok_or_else(|| TopologyError::UnknownNode(name.to_string()))
+    }
+
+    // Runs all nodes until the named node halts and returns the outputs of every node.
+    pub fn run_until_halted(
+        mut self,
+        name: &str,
+    ) -> Result<HashMap<String, Vec<i64>>, TopologyError> {
+        let target = self.index(name)?;
+        let mut successors = vec![vec![]; self.nodes.len()];
+        for (from, to) in &self.edges {
+            successors[self.index(from)?].push(self.index(to)?);
+        }
+        for (name, value) in &self.inputs {
+            let i = self.index(name)?;
+            self.nodes[i].computer.push_input(*value);
+        }
+        while !self.nodes[target].halted {
+            let mut progress = false;
+            for (i, next) in successors.iter().enumerate() {
+                let steps = self.nodes[i].computer.steps();
+                while !self.nodes[i].halted {
+                    let node = &mut self.nodes[i];
+                    match node.computer.resume() {
+                        Ok(State::Output(value)) => {
+                            for s in next {
+                                self.nodes[*s].computer.push_input(value);
+                            }
+                        }
+                        Ok(State::Halted) => node.halted = true,
+                        Ok(State::NeedsInput) => break,
+                        Err(error) => {
+                            return Err(TopologyError::Failed {
+                                node: node.name.clone(),
+                                error,
+                            })
+                        }
+                    }
+                }
+                progress |= self.nodes[i].computer.steps() != steps;
+            }
+            if !progress && !self.nodes[target].halted {
+                return Err(TopologyError::Deadlock);
+            }
+        }
+        Ok(self
+            .nodes
+            .iter_mut()
+            .map(|n| (n.name.clone(), n.computer.drain_outputs()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Outputs its first input plus every following input, until it reads 0.
+    const ADDER: [i64; 19] = [
+        3, 17, 3, 18, 1006, 18, 16, 1, 17, 18, 18, 4, 18, 1105, 1, 2, 99, 0, 0,
+    ];
+
+    #[test]
+    fn chain() {
+        let outputs = Topology::new()
+            .node("a", ADDER.to_vec(), vec![1])
+            .node("b", ADDER.to_vec(), vec![10])
+            .edge("a", "b")
+            .input("a", 5)
+            .input("a", 0)
+            .run_until_halted("a")
+            .unwrap();
+        assert_eq!(outputs["a"], vec![6]);
+        assert_eq!(outputs["b"], vec![16]);
+    }
+
+    #[test]
+    fn errors() {
+        let result = Topology::new()
+            .node("a", ADDER.to_vec(), vec![])
+            .edge("a", "b")
+            .run_until_halted("a");
+        assert_eq!(result, Err(TopologyError::UnknownNode("b".to_string())));
+        let result = Topology::new()
+            .node("a", ADDER.to_vec(), vec![])
+            .node("b", ADDER.to_vec(), vec![])
+            .edge("a", "b")
+            .edge("b", "a")
+            .run_until_halted("b");
+        assert_eq!(result, Err(TopologyError::Deadlock));
+        let result = Topology::new()
+            .node("a", vec![42], vec![])
+            .run_until_halted("a");
+        assert!(matches!(result, Err(TopologyError::Failed { .. })));
+    }
+}
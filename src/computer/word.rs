@@ -0,0 +1,156 @@
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::ops::Add;
+use std::str::FromStr;
+
+// Integer type of the memory words and I/O values of a Computer. Instructions, modes and
+// addresses must still fit in an i64, but values computed by a program can be as wide as the
+// word type allows.
+pub trait Word:
+    Copy + Eq + Ord + Hash + Debug + Display + FromStr + Add<Output = Self> + Send + Sync + 'static
+{
+    fn from_i64(value: i64) -> Self;
+    // None if the value doesn't fit in an i64.
+    fn to_i64(self) -> Option<i64>;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn saturating_add(self, other: Self) -> Self;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+    fn wrapping_mul(self, other: Self) -> Self;
+    fn saturating_mul(self, other: Self) -> Self;
+}
+
+macro_rules! primitive_word {
+    ($t:ty) => {
+        impl Word for $t {
+            fn from_i64(value: i64) -> Self {
+                value.into()
+            }
+            fn to_i64(self) -> Option<i64> {
+                use std::convert::TryFrom;
+                i64::try_from(self).ok()
+            }
+            fn checked_add(self, other: Self) -> Option<Self> {
+                <$t>::checked_add(self, other)
+            }
+            fn wrapping_add(self, other: Self) -> Self {
+                <$t>::wrapping_add(self, other)
+            }
+            fn saturating_add(self, other: Self) -> Self {
+                <$t>::saturating_add(self, other)
+            }
+            fn checked_mul(self, other: Self) -> Option<Self> {
+                <$t>::checked_mul(self, other)
+            }
+            fn wrapping_mul(self, other: Self) -> Self {
+                <$t>::wrapping_mul(self, other)
+            }
+            fn saturating_mul(self, other: Self) -> Self {
+                <$t>::saturating_mul(self, other)
+            }
+        }
+    };
+}
+
+primitive_word!(i64);
+primitive_word!(i128);
+
+// Converts a program to a wider word type, e.g. to run it with Computer::<i128>::from_words.
+pub fn widen<W: Word>(program: &[i64]) -> Vec<W> {
+    program.iter().map(|v| W::from_i64(*v)).collect()
+}
+
+// Value to report in a VmError, clamped to the i64 range.
+pub(super) fn clamp<W: Word>(value: W) -> i64 {
+    value.to_i64().unwrap_or(if value < W::from_i64(0) {
+        i64::MIN
+    } else {
+        i64::MAX
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::memory::SparseMemory;
+    use super::super::{Computer, VmError};
+    use super::*;
+
+    // Outputs (a * b) + 1.
+    fn multiply_add(a: i64, b: i64) -> Vec<i64> {
+        vec![1102, a, b, 11, 101, 1, 11, 11, 4, 11, 99, 0]
+    }
+
+    #[test]
+    fn wide_words() {
+        let program = multiply_add(1 << 40, 1 << 40);
+        let mut c = Computer::<i128>::from_words(widen(&program));
+        assert_eq!(c.run_to_halt(vec![]), Ok(vec![(1 << 80) + 1]));
+        assert_eq!(
+            Computer::new(program).run_to_halt(vec![]),
+            Err(VmError::Overflow {
+                pc: 0,
+                instruction: 1102
+            })
+        );
+    }
+
+    #[test]
+    fn widths_agree() {
+        // day 9 example that outputs a 16 digit number.
+        let program = vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0];
+        let narrow = Computer::new(program.clone()).run_to_halt(vec![]).unwrap();
+        let wide = Computer::<i128>::from_words(widen(&program))
+            .run_to_halt(vec![])
+            .unwrap();
+        assert_eq!(widen::<i128>(&narrow), wide);
+    }
+
+    #[test]
+    fn wide_addresses() {
+        // Reads an address and then a value to write there.
+        let program: Vec<i128> = widen(&[3, 3, 3, 0, 99]);
+        let mut c = Computer::from_words(program.clone());
+        assert_eq!(c.run_to_halt(vec![6, 7]), Ok(vec![]));
+        assert_eq!(c.peek(6), 7);
+        // Addresses beyond the i64 range are out of bounds, however little memory is used.
+        let mut c = Computer::from_words(program.clone());
+        assert_eq!(
+            c.run_to_halt(vec![1 << 100, 7]),
+            Err(VmError::OutOfBounds {
+                pc: 2,
+                instruction: 3,
+                address: usize::MAX
+            })
+        );
+        let mut c = Computer::from_memory(SparseMemory::from_words(&program));
+        assert!(matches!(
+            c.run_to_halt(vec![1 << 100, 7]),
+            Err(VmError::OutOfBounds {
+                address: usize::MAX,
+                ..
+            })
+        ));
+        let mut c = Computer::from_words(program);
+        assert_eq!(
+            c.run_to_halt(vec![-(1 << 100), 7]),
+            Err(VmError::NegativeAddress {
+                pc: 2,
+                instruction: 3,
+                address: i64::MIN
+            })
+        );
+        assert_eq!(clamp(1i128 << 100), i64::MAX);
+    }
+
+    #[test]
+    fn wide_instructions() {
+        let mut c = Computer::<i128>::from_words(vec![1 << 100]);
+        assert_eq!(
+            c.resume(),
+            Err(VmError::IllegalOpcode {
+                pc: 0,
+                instruction: i64::MAX
+            })
+        );
+    }
+}
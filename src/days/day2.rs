@@ -1,3 +1,4 @@
+use crate::computer::program::Program;
 use crate::puzzle::{io, File, Puzzle};
 use std::string::String;
 pub struct Day2;
@@ -9,32 +10,21 @@ impl Day2 {
         c.mem().clone()
     }
 
+    // Symbolic execution finds noun and verb directly if memory[0] is affine in them, as it is
+    // for day 2 inputs, with a search for other programs.
     fn solve_part2(&self, mem: &Vec<i64>) -> Result<String, &'static str> {
-        for noun in 0..100 {
-            for verb in 0..100 {
-                let mut mem = mem.clone();
-                mem[1] = noun;
-                mem[2] = verb;
-                let mut c = crate::computer::Computer::new(mem);
-                c.run_program(vec![0]);
-                if c.mem()[0] == 19690720 {
-                    return Ok((100 * noun + verb).to_string());
-                }
-            }
+        match crate::computer::symbolic::solve(mem, &[(1, 0..100), (2, 0..100)], 0, 19690720) {
+            Some(values) => Ok((100 * values[0] + values[1]).to_string()),
+            None => Err("No solution found"),
         }
-        Err("No solution found")
     }
 }
 
 impl Puzzle for Day2 {
     fn solve(&self, lines: io::Result<io::Lines<io::BufReader<File>>>) -> (String, String) {
-        let mut mem: Vec<i64> = lines
-            .expect("No input file")
-            .map(|l| l.unwrap())
-            .collect::<String>()
-            .split(",")
-            .map(|x| x.parse::<i64>().unwrap())
-            .collect();
+        let mut mem: Vec<i64> = Program::from_lines(lines.expect("No input file"))
+            .expect("Bad program")
+            .into_words();
         mem[1] = 12;
         mem[2] = 2;
         return (
@@ -1,3 +1,4 @@
+use crate::computer::program::Program;
 use crate::puzzle::{io, File, Puzzle};
 use std::string::String;
 pub struct Day5;
@@ -5,19 +6,20 @@ pub struct Day5;
 impl Day5 {
     fn solve(&self, mem: &Vec<i64>, input: i64) -> i64 {
         let mut c = crate::computer::Computer::new(mem.clone());
-        c.run_program(vec![input])
+        let outputs = c.run_to_halt(vec![input]).unwrap();
+        let (code, tests) = outputs.split_last().expect("No diagnostic code");
+        if let Some(i) = tests.iter().position(|x| *x != 0) {
+            panic!("Diagnostic test {} failed with {}", i, tests[i]);
+        }
+        *code
     }
 }
 
 impl Puzzle for Day5 {
     fn solve(&self, lines: io::Result<io::Lines<io::BufReader<File>>>) -> (String, String) {
-        let mem: Vec<i64> = lines
-            .expect("No input file")
-            .map(|l| l.unwrap())
-            .collect::<String>()
-            .split(",")
-            .map(|x| x.parse::<i64>().unwrap())
-            .collect();
+        let mem: Vec<i64> = Program::from_lines(lines.expect("No input file"))
+            .expect("Bad program")
+            .into_words();
         return (
             self.solve(&mem, 1).to_string(),
             self.solve(&mem, 5).to_string(),
@@ -1,28 +1,25 @@
-use crate::computer::Computer;
+use crate::computer::program::Program;
+use crate::computer::topology::Topology;
 use crate::puzzle::{io, File, Puzzle};
 use std::string::String;
 pub struct Day7;
 
 fn max_thrust(settings_left: Vec<i64>, selected_settings: Vec<i64>, program: &Vec<i64>) -> i64 {
     if settings_left.is_empty() {
-        let mut computers: Vec<Computer> = vec![];
-        let mut input = 0;
-        let mut done = false;
-        for phase in &selected_settings {
-            computers.push(Computer::new(program.clone()));
-            let c = computers.last_mut().unwrap();
-            let (halted, output) = c.run_until_output(vec![*phase, input]);
-            input = output;
-            done = halted;
+        let names = ["A", "B", "C", "D", "E"];
+        let mut amplifiers = Topology::new();
+        for (name, phase) in names.iter().zip(&selected_settings) {
+            amplifiers = amplifiers.node(name, program.clone(), vec![*phase]);
         }
-        while !done {
-            for c in &mut computers {
-                let (halted, output) = c.run_until_output(vec![input]);
-                input = output;
-                done = halted;
-            }
+        for pair in names.windows(2) {
+            amplifiers = amplifiers.edge(pair[0], pair[1]);
         }
-        input
+        let outputs = amplifiers
+            .edge("E", "A")
+            .input("A", 0)
+            .run_until_halted("E")
+            .unwrap();
+        *outputs["E"].last().unwrap()
     } else {
         let mut max = std::i64::MIN;
         for phase in &settings_left {
@@ -50,13 +47,9 @@ impl Day7 {
 
 impl Puzzle for Day7 {
     fn solve(&self, lines: io::Result<io::Lines<io::BufReader<File>>>) -> (String, String) {
-        let program: Vec<i64> = lines
-            .expect("No input file")
-            .map(|l| l.unwrap())
-            .collect::<String>()
-            .split(",")
-            .map(|x| x.parse::<i64>().unwrap())
-            .collect();
+        let program: Vec<i64> = Program::from_lines(lines.expect("No input file"))
+            .expect("Bad program")
+            .into_words();
         return (
             self.solve_part1(&program).to_string(),
             self.solve_part2(&program).to_string(),
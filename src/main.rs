@@ -5,8 +5,26 @@ use std::path::Path;
 use std::process;
 use std::time::Instant;
 
-mod computer;
+use advent_of_code_2019::computer;
+
+// The puzzle solutions predate running clippy with -D warnings and are kept as they were written.
+#[allow(
+    clippy::if_same_then_else,
+    clippy::implicit_saturating_sub,
+    clippy::iter_nth_zero,
+    clippy::legacy_numeric_constants,
+    clippy::len_zero,
+    clippy::needless_borrow,
+    clippy::needless_return,
+    clippy::op_ref,
+    clippy::ptr_arg,
+    clippy::redundant_closure,
+    clippy::useless_vec,
+    clippy::vec_box
+)]
 mod days;
+// BufRead is re-exported for the days to use.
+#[allow(unused_imports)]
 mod puzzle;
 
 #[macro_export]